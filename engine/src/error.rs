@@ -0,0 +1,74 @@
+use std::{fmt::Display, io};
+
+use crate::maps::{Position, Terrain};
+
+#[derive(Debug)]
+pub enum EmpyreError {
+    Io(io::Error),
+    UnsupportedVersion(u32),
+    InvalidMap(String),
+    SizeMismatch {
+        expected: usize,
+        found: usize,
+    },
+    OutOfBounds(Position),
+    RaggedMap {
+        row: usize,
+        expected: usize,
+        found: usize,
+    },
+    MisplacedPiece {
+        pos: Position,
+        terrain: Terrain,
+    },
+    UnreachableWaterRatio(u16),
+    InvalidTheme(String),
+    NoFreeTile(Position),
+}
+
+impl Display for EmpyreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EmpyreError::Io(err) => write!(f, "i/o error: {err}"),
+            EmpyreError::UnsupportedVersion(version) => {
+                write!(f, "unsupported save version: {version}")
+            }
+            EmpyreError::InvalidMap(reason) => write!(f, "invalid map: {reason}"),
+            EmpyreError::SizeMismatch { expected, found } => {
+                write!(f, "expected {expected} cells, found {found}")
+            }
+            EmpyreError::OutOfBounds(pos) => write!(f, "position {pos:?} is off the map"),
+            EmpyreError::RaggedMap {
+                row,
+                expected,
+                found,
+            } => write!(f, "row {row} has length {found}, expected {expected}"),
+            EmpyreError::MisplacedPiece { pos, terrain } => {
+                write!(f, "piece at {pos:?} cannot stand on {terrain:?}")
+            }
+            EmpyreError::UnreachableWaterRatio(ratio) => {
+                write!(
+                    f,
+                    "no water height gives {ratio}% water and leaves any land"
+                )
+            }
+            EmpyreError::InvalidTheme(reason) => write!(f, "invalid theme: {reason}"),
+            EmpyreError::NoFreeTile(pos) => write!(f, "no free tile for a piece near {pos:?}"),
+        }
+    }
+}
+
+impl std::error::Error for EmpyreError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EmpyreError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for EmpyreError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
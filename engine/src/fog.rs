@@ -0,0 +1,103 @@
+use crate::maps::{Grid, Location, Position, Terrain};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Visibility {
+    // Never seen; shown as Unknown terrain.
+    Unseen,
+    // Seen before but out of sight now: the last known terrain, no pieces.
+    Remembered,
+    // In sight: terrain and pieces as they are.
+    Visible,
+}
+
+// One player's picture of a map under fog of war.
+#[derive(Clone, Debug)]
+pub struct FogMap {
+    view: Grid<Location>,
+    visibility: Grid<Visibility>,
+}
+
+impl FogMap {
+    pub fn new(width: u16, height: u16) -> Self {
+        let tiles = width as usize * height as usize;
+        let unknown = Grid::from_vec(width, height, vec![Visibility::Unseen; tiles])
+            .expect("one state per tile");
+        let view = Grid::from_vec(
+            width,
+            height,
+            unknown
+                .positions_in(Position::new(0, 0), width, height)
+                .map(|pos| Location::new(pos, Terrain::Unknown, 0))
+                .collect(),
+        )
+        .expect("one location per tile");
+
+        Self {
+            view,
+            visibility: unknown,
+        }
+    }
+
+    pub fn view(&self) -> &Grid<Location> {
+        &self.view
+    }
+
+    pub fn visibility(&self, pos: Position) -> Visibility {
+        self.visibility[pos]
+    }
+
+    // Copies the tiles in `visible` from `source` and lets everything that
+    // was visible but no longer is fall back to remembered. Positions off
+    // either map are skipped.
+    pub fn update_visibility(&mut self, source: &Grid<Location>, visible: &[Position]) {
+        let all = self.visibility.positions_in(
+            Position::new(0, 0),
+            self.view.width(),
+            self.view.height(),
+        );
+        for pos in all.collect::<Vec<_>>() {
+            if self.visibility[pos] == Visibility::Visible {
+                self.visibility[pos] = Visibility::Remembered;
+                self.view.remove_piece(pos);
+            }
+        }
+
+        for pos in visible {
+            if !self.view.covers(*pos) || !source.covers(*pos) {
+                continue;
+            }
+            self.visibility[*pos] = Visibility::Visible;
+            self.view[*pos] = source[*pos].clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tile_goes_through_all_states() {
+        let source = Grid::from_ascii("+O.\n").unwrap();
+        let city = Position::new(1, 0);
+        let mut fog = FogMap::new(3, 1);
+        assert_eq!(fog.visibility(city), Visibility::Unseen);
+        assert_eq!(fog.view().to_string(), "   \n");
+
+        fog.update_visibility(&source, &[Position::new(0, 0), city]);
+        assert_eq!(fog.visibility(city), Visibility::Visible);
+        assert_eq!(fog.view().to_string(), "+O \n");
+
+        fog.update_visibility(&source, &[Position::new(2, 0)]);
+        assert_eq!(fog.visibility(city), Visibility::Remembered);
+        assert_eq!(fog.visibility(Position::new(2, 0)), Visibility::Visible);
+        assert_eq!(fog.view().to_string(), "++.\n");
+
+        fog.update_visibility(&source, &[city]);
+        assert_eq!(fog.visibility(city), Visibility::Visible);
+        assert_eq!(fog.view().to_string(), "+O.\n");
+
+        fog.update_visibility(&source, &[Position::new(3, 0), Position::new(0, -1)]);
+        assert_eq!(fog.view().to_string(), "++.\n");
+    }
+}
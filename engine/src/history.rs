@@ -0,0 +1,172 @@
+use crate::{
+    error::EmpyreError,
+    maps::{Grid, Location, Position, Terrain},
+    pieces::Piece,
+};
+
+// The tiles one edit changed, as (position, before, after).
+type Edit = Vec<(Position, Location, Location)>;
+
+// Wraps a map being authored so every edit made through it can be undone
+// and redone. Making a new edit throws away anything that could be redone.
+pub struct EditHistory {
+    grid: Grid<Location>,
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+}
+
+impl EditHistory {
+    pub fn new(grid: Grid<Location>) -> Self {
+        Self {
+            grid,
+            undo: Vec::new(),
+            redo: Vec::new(),
+        }
+    }
+
+    pub fn grid(&self) -> &Grid<Location> {
+        &self.grid
+    }
+
+    pub fn into_grid(self) -> Grid<Location> {
+        self.grid
+    }
+
+    pub fn fill(&mut self, top_left: Position, w: u16, h: u16, terrain: Terrain) {
+        self.record(|grid| grid.fill_terrain(top_left, w, h, terrain));
+    }
+
+    pub fn stamp(&mut self, other: &Grid<Location>, at: Position) -> Result<(), EmpyreError> {
+        self.record(|grid| grid.stamp(other, at))
+    }
+
+    // Checked like `stamp`: an edit that fails leaves nothing to undo.
+    pub fn place(&mut self, piece: Piece, pos: Position) -> Result<(), EmpyreError> {
+        if !self.grid.covers(pos) {
+            return Err(EmpyreError::OutOfBounds(pos));
+        }
+        let terrain = *self.grid[pos].terrain();
+        if !piece.can_occupy(&terrain) {
+            return Err(EmpyreError::MisplacedPiece { pos, terrain });
+        }
+        self.record(|grid| grid.put_piece(piece, pos));
+        Ok(())
+    }
+
+    pub fn undo(&mut self) -> bool {
+        let Some(edit) = self.undo.pop() else {
+            return false;
+        };
+        for (pos, before, _) in &edit {
+            self.grid[*pos] = before.clone();
+        }
+        self.redo.push(edit);
+        true
+    }
+
+    pub fn redo(&mut self) -> bool {
+        let Some(edit) = self.redo.pop() else {
+            return false;
+        };
+        for (pos, _, after) in &edit {
+            self.grid[*pos] = after.clone();
+        }
+        self.undo.push(edit);
+        true
+    }
+
+    // Runs `edit` and keeps the tiles it changed. Edits that change nothing
+    // are not recorded.
+    fn record<R>(&mut self, edit: impl FnOnce(&mut Grid<Location>) -> R) -> R {
+        let before = self.grid.clone();
+        let result = edit(&mut self.grid);
+
+        let changes: Edit = before
+            .positions_in(Position::new(0, 0), before.width(), before.height())
+            .filter(|pos| before[*pos] != self.grid[*pos])
+            .map(|pos| (pos, before[pos].clone(), self.grid[pos].clone()))
+            .collect();
+        if !changes.is_empty() {
+            self.undo.push(changes);
+            self.redo.clear();
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_undo_redo_fills() {
+        let original = Grid::from_ascii("....\n....\n....\n").unwrap();
+        let mut history = EditHistory::new(original.clone());
+
+        history.fill(Position::new(0, 0), 2, 2, Terrain::Land);
+        history.fill(Position::new(1, 1), 3, 2, Terrain::Land);
+        history.place(Piece::City, Position::new(3, 2)).unwrap();
+        let edited = history.grid().clone();
+        assert_eq!(edited.to_string(), "++..\n++++\n.++O\n");
+
+        while history.undo() {}
+        assert_eq!(history.grid(), &original);
+
+        while history.redo() {}
+        assert_eq!(history.grid(), &edited);
+    }
+
+    #[test]
+    fn test_undo_redo_stamp() {
+        let original = Grid::from_ascii("....\n....\n....\n").unwrap();
+        let prefab = Grid::from_ascii("+O\n++\n").unwrap();
+        let mut history = EditHistory::new(original.clone());
+
+        history.stamp(&prefab, Position::new(2, 1)).unwrap();
+        let stamped = history.grid().clone();
+        assert_eq!(stamped.to_string(), "....\n..+O\n..++\n");
+
+        assert!(history.undo());
+        assert_eq!(history.grid(), &original);
+        assert!(history.redo());
+        assert_eq!(history.grid(), &stamped);
+        assert_eq!(history.grid().to_string(), "....\n..+O\n..++\n");
+        assert!(history.grid().validate().is_ok());
+    }
+
+    #[test]
+    fn test_failed_place_is_not_recorded() {
+        let original = Grid::from_ascii("+.\n").unwrap();
+        let mut history = EditHistory::new(original.clone());
+
+        assert!(matches!(
+            history.place(Piece::City, Position::new(1, 0)),
+            Err(EmpyreError::MisplacedPiece {
+                terrain: Terrain::Water,
+                ..
+            })
+        ));
+        assert!(matches!(
+            history.place(Piece::City, Position::new(2, 0)),
+            Err(EmpyreError::OutOfBounds(_))
+        ));
+        assert_eq!(history.grid(), &original);
+        assert!(!history.undo());
+
+        history.place(Piece::City, Position::new(0, 0)).unwrap();
+        assert_eq!(history.grid().to_string(), "O.\n");
+        assert!(history.undo());
+        assert_eq!(history.grid(), &original);
+    }
+
+    #[test]
+    fn test_new_edit_clears_redo() {
+        let mut history = EditHistory::new(Grid::from_ascii("...\n").unwrap());
+        history.fill(Position::new(0, 0), 1, 1, Terrain::Land);
+        assert!(history.undo());
+
+        history.fill(Position::new(2, 0), 1, 1, Terrain::Land);
+        assert!(!history.redo());
+        assert_eq!(history.grid().to_string(), "..+\n");
+    }
+}
@@ -0,0 +1,143 @@
+use std::sync::Arc;
+
+use crate::{
+    error::EmpyreError,
+    maps::{Grid, Location, Position, Terrain},
+    pieces::Piece,
+};
+
+// Terrain that is fixed once a map has been generated. It is shared between
+// piece layers through an `Arc`, so copying game state never copies it.
+#[derive(Debug, PartialEq)]
+pub struct TerrainMap {
+    terrain: Grid<Terrain>,
+}
+
+impl TerrainMap {
+    pub fn width(&self) -> u16 {
+        self.terrain.width()
+    }
+
+    pub fn height(&self) -> u16 {
+        self.terrain.height()
+    }
+
+    pub fn terrain(&self, pos: Position) -> Option<Terrain> {
+        self.terrain.covers(pos).then(|| self.terrain[pos])
+    }
+}
+
+// The pieces standing on a shared `TerrainMap`. Cloning a layer copies the
+// pieces and shares the terrain.
+#[derive(Clone, Debug)]
+pub struct PieceLayer {
+    terrain: Arc<TerrainMap>,
+    pieces: Grid<Option<Piece>>,
+}
+
+impl PieceLayer {
+    pub fn new(terrain: Arc<TerrainMap>) -> Self {
+        let tiles = terrain.width() as usize * terrain.height() as usize;
+        let pieces = Grid::from_vec(terrain.width(), terrain.height(), vec![None; tiles])
+            .expect("one slot per terrain tile");
+        Self { terrain, pieces }
+    }
+
+    // Splits a generated map into its frozen terrain and a layer holding
+    // its pieces.
+    pub fn freeze(map: &Grid<Location>) -> Self {
+        let all = || map.positions_in(Position::new(0, 0), map.width(), map.height());
+        let terrain = Grid::from_vec(
+            map.width(),
+            map.height(),
+            all().map(|pos| *map[pos].terrain()).collect(),
+        )
+        .expect("one terrain per map tile");
+        let pieces = Grid::from_vec(
+            map.width(),
+            map.height(),
+            all().map(|pos| map[pos].piece().cloned()).collect(),
+        )
+        .expect("one slot per map tile");
+
+        Self {
+            terrain: Arc::new(TerrainMap { terrain }),
+            pieces,
+        }
+    }
+
+    pub fn terrain(&self) -> &Arc<TerrainMap> {
+        &self.terrain
+    }
+
+    pub fn piece(&self, pos: Position) -> Option<&Piece> {
+        if self.pieces.covers(pos) {
+            self.pieces[pos].as_ref()
+        } else {
+            None
+        }
+    }
+
+    pub fn put_piece(&mut self, piece: Piece, pos: Position) -> Result<(), EmpyreError> {
+        let terrain = self
+            .terrain
+            .terrain(pos)
+            .ok_or(EmpyreError::OutOfBounds(pos))?;
+        if !piece.can_occupy(&terrain) {
+            return Err(EmpyreError::MisplacedPiece { pos, terrain });
+        }
+        self.pieces[pos] = Some(piece);
+        Ok(())
+    }
+
+    pub fn remove_piece(&mut self, pos: Position) -> Option<Piece> {
+        if self.pieces.covers(pos) {
+            self.pieces[pos].take()
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layers_share_terrain() {
+        let map = Grid::from_ascii("+O.\n++.\n").unwrap();
+        let mut first = PieceLayer::freeze(&map);
+        let mut second = first.clone();
+        assert!(Arc::ptr_eq(first.terrain(), second.terrain()));
+
+        first.put_piece(Piece::City, Position::new(0, 1)).unwrap();
+        assert_eq!(second.remove_piece(Position::new(1, 0)), Some(Piece::City));
+
+        assert_eq!(first.piece(Position::new(0, 1)), Some(&Piece::City));
+        assert_eq!(first.piece(Position::new(1, 0)), Some(&Piece::City));
+        assert_eq!(second.piece(Position::new(0, 1)), None);
+        assert_eq!(second.piece(Position::new(1, 0)), None);
+        assert_eq!(first.terrain(), second.terrain());
+        assert_eq!(
+            first.terrain().terrain(Position::new(2, 0)),
+            Some(Terrain::Water)
+        );
+    }
+
+    #[test]
+    fn test_put_piece_checks_terrain() {
+        let frozen = PieceLayer::freeze(&Grid::from_ascii("O.\n").unwrap());
+        let mut layer = PieceLayer::new(Arc::clone(frozen.terrain()));
+        assert_eq!(layer.piece(Position::new(0, 0)), None);
+
+        assert!(layer.put_piece(Piece::City, Position::new(0, 0)).is_ok());
+        assert!(matches!(
+            layer.put_piece(Piece::City, Position::new(1, 0)),
+            Err(EmpyreError::MisplacedPiece { .. })
+        ));
+        assert!(matches!(
+            layer.put_piece(Piece::City, Position::new(2, 0)),
+            Err(EmpyreError::OutOfBounds(_))
+        ));
+    }
+}
@@ -1,4 +1,24 @@
+mod error;
+mod fog;
+mod history;
+mod layers;
 mod maps;
 mod pieces;
+mod render;
+mod save;
+#[cfg(test)]
+mod test_util;
+mod theme;
 
-pub use maps::{Grid, Position, DEFAULT_MAP_HEIGHT, DEFAULT_MAP_WIDTH};
+pub use error::EmpyreError;
+pub use fog::{FogMap, Visibility};
+pub use history::EditHistory;
+pub use layers::{PieceLayer, TerrainMap};
+pub use maps::{
+    translate_positions, Connectivity, ContinentTarget, Grid, Location, Movement, Position,
+    SymmetryAxis, Terrain, TerrainGenerator, TerrainStyle, Tile, UniformGenerator,
+    DEFAULT_MAP_HEIGHT, DEFAULT_MAP_WIDTH,
+};
+pub use pieces::Piece;
+pub use save::{load_archive, save_archive, SAVE_VERSION};
+pub use theme::Theme;
@@ -1,40 +1,168 @@
 use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, VecDeque},
     fmt::Display,
-    ops::{Add, Index, IndexMut},
+    io::{self, Write},
+    ops::{Add, Index, IndexMut, Sub},
 };
 
-use rand::{
-    distributions::Uniform,
-    seq::{IteratorRandom, SliceRandom},
-    Rng,
-};
+use rand::{distributions::Uniform, rngs::StdRng, seq::SliceRandom, Rng, RngCore, SeedableRng};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 
-use crate::pieces::Piece;
+use crate::{error::EmpyreError, pieces::Piece, theme::Theme};
 
 pub const DEFAULT_MAP_WIDTH: u16 = 100;
 pub const DEFAULT_MAP_HEIGHT: u16 = 60;
 
 const MAX_HEIGHT: u16 = 999;
 
-#[derive(PartialEq)]
+const SIGHT_HEIGHT_STEP: u16 = 200;
+
+const DIRS4: [Position; 4] = [
+    Position { x: 0, y: -1 },
+    Position { x: -1, y: 0 },
+    Position { x: 1, y: 0 },
+    Position { x: 0, y: 1 },
+];
+
+const DIRS8: [Position; 8] = [
+    Position { x: -1, y: -1 },
+    Position { x: 0, y: -1 },
+    Position { x: 1, y: -1 },
+    Position { x: -1, y: 0 },
+    Position { x: 1, y: 0 },
+    Position { x: -1, y: 1 },
+    Position { x: 0, y: 1 },
+    Position { x: 1, y: 1 },
+];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum Terrain {
     Water,
     Land,
     Unknown,
+    // Impassable to everything.
+    Mountain,
 }
 
 impl Display for Terrain {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let c = match self {
-            Terrain::Land => '+',
-            Terrain::Water => '.',
-            Terrain::Unknown => ' ',
-        };
-        write!(f, "{c}")?;
+        write!(f, "{}", self.glyph(&TerrainStyle::default()))?;
         Ok(())
     }
 }
 
+impl Terrain {
+    pub fn to_bytes(&self) -> [u8; 1] {
+        match self {
+            Terrain::Water => [0],
+            Terrain::Land => [1],
+            Terrain::Unknown => [2],
+            Terrain::Mountain => [3],
+        }
+    }
+
+    pub fn from_bytes(bytes: [u8; 1]) -> Result<Self, EmpyreError> {
+        match bytes {
+            [0] => Ok(Terrain::Water),
+            [1] => Ok(Terrain::Land),
+            [2] => Ok(Terrain::Unknown),
+            [3] => Ok(Terrain::Mountain),
+            [tag] => Err(EmpyreError::InvalidMap(format!(
+                "unknown terrain tag {tag}"
+            ))),
+        }
+    }
+
+    pub fn glyph(&self, style: &TerrainStyle) -> char {
+        match self {
+            Terrain::Land => style.land,
+            Terrain::Water => style.water,
+            Terrain::Unknown => style.unknown,
+            Terrain::Mountain => style.mountain,
+        }
+    }
+
+    pub fn is_passable(&self) -> bool {
+        *self != Terrain::Mountain
+    }
+
+    // Reads the glyphs of the default style, which `Display` writes.
+    fn from_glyph(c: char) -> Option<Self> {
+        let style = TerrainStyle::default();
+        [
+            Terrain::Water,
+            Terrain::Land,
+            Terrain::Unknown,
+            Terrain::Mountain,
+        ]
+        .into_iter()
+        .find(|terrain| terrain.glyph(&style) == c)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TerrainStyle {
+    pub land: char,
+    pub water: char,
+    pub unknown: char,
+    pub mountain: char,
+}
+
+impl Default for TerrainStyle {
+    fn default() -> Self {
+        Self {
+            land: '+',
+            water: '.',
+            unknown: ' ',
+            mountain: '^',
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ContinentTarget {
+    pub count: usize,
+    pub min_size: usize,
+    pub retries: u32,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Connectivity {
+    // Orthogonal steps only.
+    Four,
+    // Orthogonal and diagonal steps.
+    Eight,
+}
+
+// The steps `find_path_with` may take.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Movement {
+    pub connectivity: Connectivity,
+    // Whether a diagonal step may squeeze between two impassable tiles.
+    pub cut_corners: bool,
+}
+
+impl Default for Movement {
+    fn default() -> Self {
+        Self {
+            connectivity: Connectivity::Eight,
+            cut_corners: true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SymmetryAxis {
+    // Top and bottom halves mirror each other.
+    Horizontal,
+    // Left and right halves mirror each other.
+    Vertical,
+    // The map looks the same rotated by 180 degrees.
+    Point,
+}
+
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub struct Position {
     x: i16,
@@ -46,8 +174,35 @@ impl Position {
         Self { x, y }
     }
 
-    fn distance(&self, other: &Self) -> usize {
-        isqrt(((other.x - self.x) ^ 2 + (other.y - self.y) ^ 2) as usize)
+    pub fn to_bytes(&self) -> [u8; 4] {
+        let [x0, x1] = self.x.to_le_bytes();
+        let [y0, y1] = self.y.to_le_bytes();
+        [x0, x1, y0, y1]
+    }
+
+    pub fn from_bytes(bytes: [u8; 4]) -> Self {
+        Self {
+            x: i16::from_le_bytes([bytes[0], bytes[1]]),
+            y: i16::from_le_bytes([bytes[2], bytes[3]]),
+        }
+    }
+
+    pub fn distance(&self, other: &Self) -> usize {
+        let dx = (other.x - self.x) as i32;
+        let dy = (other.y - self.y) as i32;
+        isqrt((dx * dx + dy * dy) as usize)
+    }
+
+    // Euclidean distance with each axis stretched by its scale, e.g. to allow
+    // for terminal cells being about twice as tall as they are wide.
+    pub fn distance_weighted(&self, other: &Self, x_scale: u16, y_scale: u16) -> usize {
+        let dx = (other.x - self.x) as i64 * x_scale as i64;
+        let dy = (other.y - self.y) as i64 * y_scale as i64;
+        isqrt((dx * dx + dy * dy) as usize)
+    }
+
+    pub fn chebyshev(&self, other: &Self) -> i16 {
+        (other.x - self.x).abs().max((other.y - self.y).abs())
     }
 }
 
@@ -62,20 +217,66 @@ impl Add for Position {
     }
 }
 
+impl Sub for Position {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self {
+            x: self.x - rhs.x,
+            y: self.y - rhs.y,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
 pub struct Location {
     pos: Position,
     terrain: Terrain,
+    height: u16,
     piece: Option<Piece>,
 }
 
 impl Location {
-    fn new(pos: Position, terrain: Terrain) -> Self {
+    pub(crate) fn new(pos: Position, terrain: Terrain, height: u16) -> Self {
         Self {
             pos,
             terrain,
+            height,
             piece: None,
         }
     }
+
+    pub fn terrain(&self) -> &Terrain {
+        &self.terrain
+    }
+
+    pub fn position(&self) -> Position {
+        self.pos
+    }
+
+    pub fn piece(&self) -> Option<&Piece> {
+        self.piece.as_ref()
+    }
+}
+
+// Something a grid can hold. `stamp`, `crop` and `transpose` tell each tile
+// where it ends up, so tiles that carry their own position stay in step.
+pub trait Tile: Clone {
+    fn moved_to(&mut self, _pos: Position) {}
+}
+
+macro_rules! plain_tiles {
+    ($($t:ty),*) => {
+        $(impl Tile for $t {})*
+    };
+}
+
+plain_tiles!(bool, char, u8, u16, u32, u64, usize, i8, i16, i32, i64, isize, f32, f64);
+
+impl Tile for Location {
+    fn moved_to(&mut self, pos: Position) {
+        self.pos = pos;
+    }
 }
 
 impl Display for Location {
@@ -88,7 +289,7 @@ impl Display for Location {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Grid<T> {
     width: u16,
     height: u16,
@@ -103,57 +304,392 @@ where
         Self {
             width,
             height,
-            map: vec![T::default(); (width * height) as usize],
+            map: vec![T::default(); width as usize * height as usize],
+        }
+    }
+}
+
+impl<T> Grid<T>
+where
+    T: Tile,
+{
+    // Stamping an empty grid changes nothing, wherever it is put.
+    pub fn stamp(&mut self, other: &Grid<T>, at: Position) -> Result<(), EmpyreError> {
+        if other.map.is_empty() {
+            return Ok(());
+        }
+        self.check_block(at, other.width, other.height)?;
+
+        for pos in other.positions_in(Position { x: 0, y: 0 }, other.width, other.height) {
+            let mut tile = other[pos].clone();
+            tile.moved_to(at + pos);
+            self[at + pos] = tile;
+        }
+        Ok(())
+    }
+
+    // Copies out the `w` x `h` block whose top left corner is `top_left`. A
+    // block with no rows or columns crops to an empty grid.
+    pub fn crop(&self, top_left: Position, w: u16, h: u16) -> Result<Grid<T>, EmpyreError> {
+        self.crop_with_offset(top_left, w, h)
+            .map(|(cropped, _)| cropped)
+    }
+
+    // Like `crop`, but also returns the offset that `translate_positions`
+    // needs to carry positions on this grid over to the cropped one.
+    pub fn crop_with_offset(
+        &self,
+        top_left: Position,
+        w: u16,
+        h: u16,
+    ) -> Result<(Grid<T>, Position), EmpyreError> {
+        if w > 0 && h > 0 {
+            self.check_block(top_left, w, h)?;
+        }
+
+        let offset = Position { x: 0, y: 0 } - top_left;
+        let cropped = Grid {
+            width: w,
+            height: h,
+            map: self
+                .positions_in(top_left, w, h)
+                .map(|pos| {
+                    let mut tile = self[pos].clone();
+                    tile.moved_to(pos + offset);
+                    tile
+                })
+                .collect(),
+        };
+        Ok((cropped, offset))
+    }
+
+    pub fn transpose(&self) -> Grid<T> {
+        Grid {
+            width: self.height,
+            height: self.width,
+            map: (0..self.map.len())
+                .map(|idx| {
+                    let pos = idx_to_pos(idx, self.height);
+                    let mut tile = self[Position { x: pos.y, y: pos.x }].clone();
+                    tile.moved_to(pos);
+                    tile
+                })
+                .collect(),
         }
     }
 }
 
 impl<T> Grid<T> {
-    fn covers(&self, pos: Position) -> bool {
+    pub fn from_vec(width: u16, height: u16, data: Vec<T>) -> Result<Self, EmpyreError> {
+        let expected = width as usize * height as usize;
+        if data.len() != expected {
+            return Err(EmpyreError::SizeMismatch {
+                expected,
+                found: data.len(),
+            });
+        }
+
+        Ok(Self {
+            width,
+            height,
+            map: data,
+        })
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    // Row-major access for callers that work with indices into the grid.
+    pub fn index_linear(&self, idx: usize) -> Option<&T> {
+        self.map.get(idx)
+    }
+
+    pub fn position_of(&self, idx: usize) -> Option<Position> {
+        (idx < self.map.len()).then(|| idx_to_pos(idx, self.width))
+    }
+
+    pub(crate) fn covers(&self, pos: Position) -> bool {
         (pos.x >= 0 && pos.x < self.width as i16) && (pos.y >= 0 && pos.y < self.height as i16)
     }
+
+    // Fails with the first corner of a non-empty `w` x `h` block, top left
+    // then bottom right, that lies off the grid.
+    fn check_block(&self, top_left: Position, w: u16, h: u16) -> Result<(), EmpyreError> {
+        if !self.covers(top_left) {
+            return Err(EmpyreError::OutOfBounds(top_left));
+        }
+        let far_corner = Position {
+            x: (top_left.x as i32 + w as i32 - 1).min(i16::MAX as i32) as i16,
+            y: (top_left.y as i32 + h as i32 - 1).min(i16::MAX as i32) as i16,
+        };
+        if !self.covers(far_corner) {
+            return Err(EmpyreError::OutOfBounds(far_corner));
+        }
+        Ok(())
+    }
+
+    pub fn positions_in(
+        &self,
+        top_left: Position,
+        w: u16,
+        h: u16,
+    ) -> impl Iterator<Item = Position> {
+        // Worked out in i32 so that wide requests cannot overflow an i16.
+        let clip = |start: i16, len: u16, size: u16| {
+            let end = (start as i32 + len as i32).min(size as i32);
+            start.max(0)..end.max(0) as i16
+        };
+        let xs = clip(top_left.x, w, self.width);
+        let ys = clip(top_left.y, h, self.height);
+        ys.flat_map(move |y| xs.clone().map(move |x| Position { x, y }))
+    }
+
+    // Positions in order of increasing Chebyshev distance from `center`, one
+    // square ring at a time, each ring in row-major order.
+    pub fn spiral_from(&self, center: Position) -> impl Iterator<Item = Position> + '_ {
+        let max_radius = center
+            .x
+            .max(self.width as i16 - 1 - center.x)
+            .max(center.y)
+            .max(self.height as i16 - 1 - center.y);
+
+        (0..=max_radius)
+            .flat_map(move |r| {
+                (center.y - r..=center.y + r).flat_map(move |y| {
+                    let step = if (y - center.y).abs() == r { 1 } else { 2 * r };
+                    (center.x - r..=center.x + r)
+                        .step_by(step as usize)
+                        .map(move |x| Position { x, y })
+                })
+            })
+            .filter(|pos| self.covers(*pos))
+    }
+
+    // The outermost ring, clockwise from the top left: top row, right column,
+    // bottom row, then left column, with each corner yielded once. A grid
+    // with no rows or no columns has no edge.
+    pub fn edge_tiles(&self) -> impl Iterator<Item = Position> {
+        let (right, bottom) = if self.width == 0 || self.height == 0 {
+            (-1, -1)
+        } else {
+            (self.width as i16 - 1, self.height as i16 - 1)
+        };
+        let top = (0..=right).map(|x| Position { x, y: 0 });
+        let side = (1..=bottom).map(move |y| Position { x: right, y });
+        let base = (0..right)
+            .rev()
+            .map(move |x| Position { x, y: bottom })
+            .filter(move |_| bottom > 0);
+        let left = (1..bottom)
+            .rev()
+            .map(|y| Position { x: 0, y })
+            .filter(move |_| right > 0);
+        top.chain(side).chain(base).chain(left)
+    }
+
+    pub fn mirror(&self, pos: Position, axis: SymmetryAxis) -> Position {
+        let (far_x, far_y) = (
+            self.width as i16 - 1 - pos.x,
+            self.height as i16 - 1 - pos.y,
+        );
+        match axis {
+            SymmetryAxis::Horizontal => Position { x: pos.x, y: far_y },
+            SymmetryAxis::Vertical => Position { x: far_x, y: pos.y },
+            SymmetryAxis::Point => Position { x: far_x, y: far_y },
+        }
+    }
+
+    pub fn affected_tiles(&self, center: Position, radius: u16) -> Vec<Position> {
+        let r = radius as i32;
+        let corner = |c: i16| (c as i32 - r).max(0) as i16;
+        let span = |c: i16| (c as i32 + r + 1 - corner(c) as i32).clamp(0, u16::MAX as i32) as u16;
+        self.positions_in(
+            Position {
+                x: corner(center.x),
+                y: corner(center.y),
+            },
+            span(center.x),
+            span(center.y),
+        )
+        .collect()
+    }
+}
+
+impl<T> Grid<T> {
+    // The number of in-bounds neighbours only depends on the grid's dimensions.
+    fn neighbour_counts(&self) -> Grid<u8> {
+        Grid {
+            width: self.width,
+            height: self.height,
+            map: (0..self.map.len())
+                .map(|idx| self.neighbours(idx_to_pos(idx, self.width)).count() as u8)
+                .collect(),
+        }
+    }
 }
 
 impl<'g, T> Grid<T> {
+    pub fn neighbour_positions(&self, pos: Position) -> impl Iterator<Item = Position> {
+        self.neighbour_positions_with(pos, Connectivity::Eight)
+    }
+
+    pub fn neighbour_positions_with(
+        &self,
+        pos: Position,
+        connectivity: Connectivity,
+    ) -> impl Iterator<Item = Position> {
+        self.positions_in(pos - Position { x: 1, y: 1 }, 3, 3)
+            .filter(move |n_pos| *n_pos != pos)
+            .filter(move |n_pos| {
+                connectivity == Connectivity::Eight || n_pos.x == pos.x || n_pos.y == pos.y
+            })
+    }
+
+    // `pos` itself plus its in-bounds neighbours, in row-major order.
+    pub fn block3(&'g self, pos: Position) -> impl Iterator<Item = (Position, &'g T)> {
+        self.positions_in(pos - Position { x: 1, y: 1 }, 3, 3)
+            .map(move |b_pos| (b_pos, &self[b_pos]))
+    }
+
+    // In-bounds neighbours nearest `goal` first, ties in reading order.
+    pub fn neighbours_toward(
+        &'g self,
+        pos: Position,
+        goal: Position,
+    ) -> impl Iterator<Item = (Position, &'g T)> {
+        let mut positions: Vec<Position> = self.neighbour_positions(pos).collect();
+        positions.sort_by_key(|n_pos| {
+            let (dx, dy) = ((goal.x - n_pos.x) as i32, (goal.y - n_pos.y) as i32);
+            dx * dx + dy * dy
+        });
+        positions
+            .into_iter()
+            .map(move |n_pos| (n_pos, &self[n_pos]))
+    }
+
     pub fn neighbours(&'g self, pos: Position) -> NeighbourIter<'g, T> {
+        self.neighbours_with(pos, Connectivity::Eight)
+    }
+
+    pub fn neighbours_with(
+        &'g self,
+        pos: Position,
+        connectivity: Connectivity,
+    ) -> NeighbourIter<'g, T> {
+        let dirs: &'static [Position] = match connectivity {
+            Connectivity::Four => &DIRS4,
+            Connectivity::Eight => &DIRS8,
+        };
+
         NeighbourIter::<'g, T> {
-            grid: &self,
+            grid: self,
             pos,
-            dirs: vec![
-                Position { x: -1, y: -1 },
-                Position { x: 0, y: -1 },
-                Position { x: 1, y: -1 },
-                Position { x: -1, y: 0 },
-                Position { x: 1, y: 0 },
-                Position { x: -1, y: 1 },
-                Position { x: 0, y: 1 },
-                Position { x: 1, y: 1 },
-            ],
+            dirs,
             idx: 0,
         }
     }
 }
 
-impl Grid<u16> {
-    pub fn new_random(width: u16, height: u16) -> Self {
-        let rng = rand::thread_rng();
+pub trait TerrainGenerator {
+    fn generate(&self, width: u16, height: u16, rng: &mut dyn RngCore) -> Grid<u16>;
+}
 
+// Independent, uniformly distributed heights; smoothing turns them into land.
+pub struct UniformGenerator;
+
+impl TerrainGenerator for UniformGenerator {
+    fn generate(&self, width: u16, height: u16, rng: &mut dyn RngCore) -> Grid<u16> {
         let mut grid = Grid::<u16>::new(width, height);
         grid.map = rng
             .sample_iter(Uniform::from(0..MAX_HEIGHT))
-            .take(grid.map.capacity())
+            .take(grid.map.len())
             .collect();
+        assert_eq!(grid.map.len(), width as usize * height as usize);
+        grid
+    }
+}
+
+impl Grid<bool> {
+    pub fn lock_region(&mut self, top_left: Position, w: u16, h: u16) {
+        for pos in self.positions_in(top_left, w, h).collect::<Vec<_>>() {
+            self[pos] = true;
+        }
+    }
+}
+
+impl Grid<u16> {
+    pub fn new_random(width: u16, height: u16) -> Self {
+        Self::new_random_with(width, height, &mut rand::thread_rng())
+    }
+
+    pub fn new_random_with(width: u16, height: u16, rng: &mut impl Rng) -> Self {
+        UniformGenerator.generate(width, height, rng)
+    }
+
+    // Smoothing and thresholding are mirror-invariant, so terrain made from
+    // this grid keeps the same symmetry.
+    pub fn new_symmetric(width: u16, height: u16, seed: u64, axis: SymmetryAxis) -> Self {
+        let mut grid = Self::new_random_with(width, height, &mut StdRng::seed_from_u64(seed));
+        for idx in 0..grid.map.len() {
+            let mirror = grid.mirror(idx_to_pos(idx, width), axis);
+            if pos_to_idx(mirror, width) < idx {
+                grid.map[idx] = grid[mirror];
+            }
+        }
         grid
     }
 
     pub fn smooth(self) -> Self {
-        let mut new_map = self.map.clone();
-        for idx in 0..new_map.len() {
-            let pos = idx_to_pos(idx, self.width);
-            new_map[idx] = (self.neighbours(pos).sum::<u16>() + self[pos])
-                / (self.neighbours(pos).count() + 1) as u16;
+        let counts = self.neighbour_counts();
+        self.smooth_with(&counts)
+    }
+
+    pub fn smooth_passes(self, passes: u16) -> Self {
+        let counts = self.neighbour_counts();
+        (0..passes).fold(self, |grid, _| grid.smooth_with(&counts))
+    }
+
+    // Like `smooth_passes`, but tiles marked in `locked` keep their height.
+    // Locked tiles still feed into their neighbours' averages.
+    pub fn smooth_masked(self, passes: u16, locked: &Grid<bool>) -> Result<Self, EmpyreError> {
+        if (locked.width, locked.height) != (self.width, self.height) {
+            return Err(EmpyreError::SizeMismatch {
+                expected: self.map.len(),
+                found: locked.map.len(),
+            });
         }
 
+        let counts = self.neighbour_counts();
+        let original = self.map.clone();
+        Ok((0..passes).fold(self, |grid, _| {
+            let mut smoothed = grid.smooth_with(&counts);
+            for (idx, height) in smoothed.map.iter_mut().enumerate() {
+                if locked.map[idx] {
+                    *height = original[idx];
+                }
+            }
+            smoothed
+        }))
+    }
+
+    // Each cell only reads the old map, so the parallel version gives exactly
+    // the same result as the sequential one.
+    fn smooth_with(self, counts: &Grid<u8>) -> Self {
+        #[cfg(feature = "parallel")]
+        let new_map = (0..self.map.len())
+            .into_par_iter()
+            .map(|idx| self.smoothed_cell(counts, idx))
+            .collect();
+        #[cfg(not(feature = "parallel"))]
+        let new_map = (0..self.map.len())
+            .map(|idx| self.smoothed_cell(counts, idx))
+            .collect();
+
         Self {
             width: self.width,
             height: self.height,
@@ -161,19 +697,121 @@ impl Grid<u16> {
         }
     }
 
-    fn water_height(&self, ratio: u16) -> u16 {
-        for h in 0..MAX_HEIGHT {
-            let below = self.map.iter().filter(|level| **level <= h).count();
-            let above = self.map.iter().filter(|level| **level > h).count();
-            if below * 100 / (above + below) > ratio as usize {
-                return h;
+    fn smoothed_cell(&self, counts: &Grid<u8>, idx: usize) -> u16 {
+        let pos = idx_to_pos(idx, self.width);
+        let divisor = counts.map[idx] as u32 + 1;
+        let sum = self.block3(pos).map(|(_, h)| *h as u32).sum::<u32>();
+        ((sum + divisor / 2) / divisor) as u16
+    }
+
+    // Scales heights down with distance from the centre so land gathers in
+    // the middle of the map. A `strength` of 0.0 leaves the heights alone,
+    // letting land reach the edges; 1.0 brings the edges down to zero for an
+    // island world.
+    pub fn center_bias(self, strength: f32) -> Self {
+        let strength = strength.clamp(0.0, 1.0);
+        let (half_w, half_h) = (self.width as f32 / 2.0, self.height as f32 / 2.0);
+        let map = self
+            .map
+            .iter()
+            .enumerate()
+            .map(|(idx, level)| {
+                let pos = idx_to_pos(idx, self.width);
+                let dx = (pos.x as f32 + 0.5 - half_w) / half_w;
+                let dy = (pos.y as f32 + 0.5 - half_h) / half_h;
+                let falloff = (1.0 - strength * (dx * dx + dy * dy).sqrt()).max(0.0);
+                (*level as f32 * falloff).round() as u16
+            })
+            .collect();
+
+        Self {
+            width: self.width,
+            height: self.height,
+            map,
+        }
+    }
+
+    // Heights rescaled so the lowest on the map is 0.0 and the highest 1.0.
+    // A flat map comes out all 0.0.
+    pub fn normalized(&self) -> Grid<f32> {
+        let min = self.map.iter().copied().min().unwrap_or(0);
+        let max = self.map.iter().copied().max().unwrap_or(0);
+        let range = (max - min).max(1) as f32;
+        Grid {
+            width: self.width,
+            height: self.height,
+            map: self
+                .map
+                .iter()
+                .map(|level| (level - min) as f32 / range)
+                .collect(),
+        }
+    }
+
+    // Central differences of the heights either side of `pos`, falling back to
+    // one-sided differences at the edges. Positive components point uphill.
+    pub fn gradient(&self, pos: Position) -> (i32, i32) {
+        let clamp = |p: Position| if self.covers(p) { p } else { pos };
+        let diff = |dir: Position| {
+            let (before, after) = (clamp(pos - dir), clamp(pos + dir));
+            let span = (after.x - before.x + after.y - before.y) as i32;
+            if span == 0 {
+                0
+            } else {
+                (self[after] as i32 - self[before] as i32) / span
             }
+        };
+        (diff(Position { x: 1, y: 0 }), diff(Position { x: 0, y: 1 }))
+    }
+
+    fn water_height(&self, ratio: u16) -> u16 {
+        self.water_threshold(ratio).map_or(u16::MAX, |(h, _)| h)
+    }
+
+    // Like `water_height`, but fails rather than drowning the whole map when
+    // no height leaves any land, e.g. on a flat heightmap.
+    pub fn try_water_height(&self, ratio: u16) -> Result<u16, EmpyreError> {
+        match self.water_threshold(ratio) {
+            Some((h, below)) if below < self.map.len() => Ok(h),
+            _ => Err(EmpyreError::UnreachableWaterRatio(ratio)),
         }
-        MAX_HEIGHT
+    }
+
+    // The lowest height that puts more than `ratio` percent of tiles under
+    // water, along with how many tiles that is. Only heights that occur in
+    // the map can be the answer, so heights from `from_vec` above
+    // MAX_HEIGHT work too.
+    fn water_threshold(&self, ratio: u16) -> Option<(u16, usize)> {
+        let mut sorted = self.map.clone();
+        sorted.sort_unstable();
+        (0..sorted.len())
+            .filter(|idx| sorted.get(idx + 1) != Some(&sorted[*idx]))
+            .map(|idx| (sorted[idx], idx + 1))
+            .find(|(_, below)| below * 100 / sorted.len() > ratio as usize)
     }
 
     pub fn make_terrain(self, water: u16) -> Grid<Location> {
         let wh = self.water_height(water);
+        self.classify_at(wh)
+    }
+
+    // Like `make_terrain`, but aims for `land_percent` land by counting down
+    // from the highest tiles: the water height is the highest one that still
+    // leaves at least that much land above it.
+    pub fn make_terrain_land_ratio(self, land_percent: u16) -> Grid<Location> {
+        let mut sorted = self.map.clone();
+        sorted.sort_unstable_by(|a, b| b.cmp(a));
+        // Keeping the `needed` highest tiles dry means flooding up to just
+        // below the lowest of them.
+        let needed = (land_percent as usize * sorted.len()).div_ceil(100);
+        let wh = match needed {
+            0 => u16::MAX,
+            n => sorted.get(n - 1).map_or(0, |h| h.saturating_sub(1)),
+        };
+        self.classify_at(wh)
+    }
+
+    fn classify_at(self, wh: u16) -> Grid<Location> {
         Grid {
             width: self.width,
             height: self.height,
@@ -182,11 +820,7 @@ impl Grid<u16> {
                 .iter()
                 .enumerate()
                 .map(|(idx, level)| {
-                    if *level <= wh {
-                        Location::new(idx_to_pos(idx, self.width), Terrain::Water)
-                    } else {
-                        Location::new(idx_to_pos(idx, self.width), Terrain::Land)
-                    }
+                    Location::new(idx_to_pos(idx, self.width), classify(*level, wh), *level)
                 })
                 .collect(),
         }
@@ -194,253 +828,3055 @@ impl Grid<u16> {
 }
 
 impl Grid<Location> {
-    pub fn place_cities(&mut self) {
-        let (city_idx, min_city_dist) = {
-            let city_num = ((100 * (self.width + self.height)) / 228) as usize;
-
-            let city_idx: Vec<_> = (0..self.map.len())
-                .filter(|idx| self.map[*idx].terrain == Terrain::Land)
-                .choose_multiple(
-                    &mut rand::thread_rng(),
-                    ((100 * (self.width + self.height)) / 228) as usize,
-                )
-                .into_iter()
-                .map(|idx| idx_to_pos(idx, self.width))
-                .collect();
-
-            let land = self
-                .map
+    // Generates maps until one has `target.count` continents of at least
+    // `target.min_size` tiles, giving up after `target.retries` attempts.
+    // Returns the closest map found along with its continent count.
+    pub fn generate_with_continents(
+        width: u16,
+        height: u16,
+        water: u16,
+        smooth: u16,
+        target: &ContinentTarget,
+        rng: &mut impl Rng,
+    ) -> (Self, usize) {
+        let mut generate = || {
+            let map = Grid::new_random_with(width, height, rng)
+                .smooth_passes(smooth)
+                .make_terrain(water);
+            let count = map
+                .landmasses()
                 .iter()
-                .filter(|l| l.terrain == Terrain::Land)
-                .count()
-                / city_num;
-            (city_idx, isqrt(land))
+                .filter(|continent| continent.len() >= target.min_size)
+                .count();
+            (map, count)
         };
 
-        for pos in city_idx {
-            self.put_piece(Piece::City, pos);
+        let mut best = generate();
+        for _ in 1..target.retries {
+            if best.1 == target.count {
+                break;
+            }
+            let candidate = generate();
+            if candidate.1.abs_diff(target.count) < best.1.abs_diff(target.count) {
+                best = candidate;
+            }
         }
+        best
     }
 
-    fn put_piece(&mut self, piece: Piece, pos: Position) {
-        self[pos].piece = Some(piece);
+    pub fn place_cities(&mut self) {
+        self.place_cities_with(&mut rand::thread_rng())
     }
 
-    fn remove_piece(&mut self, pos: Position) {
-        self[pos].piece = None;
+    // Places nothing on maps too small for any cities or without land.
+    pub fn place_cities_with(&mut self, rng: &mut impl Rng) {
+        let city_num = self.city_count();
+        if city_num == 0 {
+            return;
+        }
+        let land = self
+            .map
+            .iter()
+            .filter(|l| l.terrain == Terrain::Land)
+            .count()
+            / city_num;
+
+        for pos in self.plan_cities(city_num, isqrt(land), rng) {
+            self.put_piece(Piece::City, pos);
+        }
     }
-}
 
-impl<T> Index<Position> for Grid<T> {
-    type Output = T;
+    // Picks up to `count` empty land tiles at least `min_dist` apart, in random
+    // order, without placing anything. Fewer are returned if the land runs out.
+    pub fn plan_cities(&self, count: usize, min_dist: usize, rng: &mut impl Rng) -> Vec<Position> {
+        let mut candidates: Vec<_> = self
+            .map
+            .iter()
+            .filter(|l| l.terrain == Terrain::Land && l.piece.is_none())
+            .map(|l| l.pos)
+            .collect();
+        candidates.shuffle(rng);
 
-    fn index(&self, index: Position) -> &Self::Output {
-        &self.map[pos_to_idx(index, self.width)]
+        let mut cities: Vec<Position> = Vec::with_capacity(count);
+        for pos in candidates {
+            if cities.len() == count {
+                break;
+            }
+            if cities.iter().all(|city| city.distance(&pos) >= min_dist) {
+                cities.push(pos);
+            }
+        }
+        cities
     }
+
+    // How good each free land tile would be for a new city, from 0.0 up to
+    // 1.0. Being far from other cities, on a big continent and near the
+    // coast all count; water and occupied tiles score 0.0.
+    pub fn buildability(&self) -> Grid<f32> {
+        const CITY_SPACING: f32 = 10.0;
+
+        let cities: Vec<Position> = self.pieces().map(|(pos, _)| pos).collect();
+        let to_water = self.distance_to_water();
+        let mut continent_size = vec![0; self.map.len()];
+        let landmasses = self.landmasses();
+        let largest = landmasses.iter().map(|l| l.len()).max().unwrap_or(1) as f32;
+        for landmass in &landmasses {
+            for pos in landmass {
+                continent_size[pos_to_idx(*pos, self.width)] = landmass.len();
+            }
+        }
+
+        Grid {
+            width: self.width,
+            height: self.height,
+            map: self
+                .map
+                .iter()
+                .enumerate()
+                .map(|(idx, location)| {
+                    if location.terrain != Terrain::Land || location.piece.is_some() {
+                        return 0.0;
+                    }
+                    let spacing = cities
+                        .iter()
+                        .map(|city| city.distance(&location.pos) as f32 / CITY_SPACING)
+                        .fold(1.0, f32::min);
+                    let coast = 1.0 / to_water.map[idx].max(1) as f32;
+                    let size = continent_size[idx] as f32 / largest;
+                    spacing * size * (1.0 + coast) / 2.0
+                })
+                .collect(),
+        }
+    }
+
+    // Picks cities in one half of the map and mirrors each of them into the
+    // other half, so that neither side gets a better share. A tile is only
+    // used when both it and its mirror image could take a city, and the
+    // cities keep the same spacing as `place_cities_with`, mirrors included.
+    pub fn place_cities_symmetric(&mut self, axis: SymmetryAxis, rng: &mut impl Rng) {
+        let city_num = self.city_count();
+        if city_num < 2 {
+            return;
+        }
+        let land = self
+            .map
+            .iter()
+            .filter(|l| l.terrain == Terrain::Land)
+            .count()
+            / city_num;
+        let min_dist = isqrt(land);
+
+        let free = |location: &Location| {
+            location.piece.is_none() && Piece::City.can_occupy(&location.terrain)
+        };
+        let mut candidates: Vec<Position> = (0..self.map.len())
+            .filter(|idx| {
+                let mirror = self.mirror(idx_to_pos(*idx, self.width), axis);
+                pos_to_idx(mirror, self.width) > *idx
+                    && free(&self.map[*idx])
+                    && free(&self[mirror])
+            })
+            .map(|idx| idx_to_pos(idx, self.width))
+            .collect();
+        candidates.shuffle(rng);
+
+        let mut cities: Vec<Position> = Vec::with_capacity(city_num);
+        for pos in candidates {
+            if cities.len() + 2 > city_num {
+                break;
+            }
+            let pair = [pos, self.mirror(pos, axis)];
+            if pair[0].distance(&pair[1]) >= min_dist
+                && cities
+                    .iter()
+                    .all(|city| pair.iter().all(|p| city.distance(p) >= min_dist))
+            {
+                cities.extend(pair);
+            }
+        }
+        for pos in cities {
+            self.put_piece(Piece::City, pos);
+        }
+    }
+
+    fn city_count(&self) -> usize {
+        100 * (self.width as usize + self.height as usize) / 228
+    }
+
+    // Recomputes land and water from the heights kept by `make_terrain`.
+    // Mountains and unknown tiles stay as they are, and so does any tile whose
+    // piece could not stand on its new terrain. Maps read with `from_ascii`
+    // or loaded from a file have no heights, so they are refused unchanged.
+    pub fn reclassify(&mut self, water_ratio: u16) -> Result<(), EmpyreError> {
+        let heights = Grid {
+            width: self.width,
+            height: self.height,
+            map: self.map.iter().map(|location| location.height).collect(),
+        };
+        let wh = heights.try_water_height(water_ratio)?;
+        for location in self.map.iter_mut() {
+            if !matches!(location.terrain, Terrain::Land | Terrain::Water) {
+                continue;
+            }
+            let terrain = classify(location.height, wh);
+            if location
+                .piece
+                .as_ref()
+                .is_some_and(|piece| !piece.can_occupy(&terrain))
+            {
+                continue;
+            }
+            location.terrain = terrain;
+        }
+        Ok(())
+    }
+
+    pub fn land_mask(&self) -> Grid<bool> {
+        Grid {
+            width: self.width,
+            height: self.height,
+            map: self
+                .map
+                .iter()
+                .map(|location| location.terrain == Terrain::Land)
+                .collect(),
+        }
+    }
+
+    // The reverse of `land_mask`: set tiles become land, the rest water.
+    // Like `reclassify`, only land and water tiles change; mountains and
+    // unknown tiles keep their terrain whatever the mask says. Pieces left
+    // on terrain they cannot occupy are removed.
+    pub fn apply_land_mask(&mut self, mask: &Grid<bool>) -> Result<(), EmpyreError> {
+        if (mask.width, mask.height) != (self.width, self.height) {
+            return Err(EmpyreError::SizeMismatch {
+                expected: self.map.len(),
+                found: mask.map.len(),
+            });
+        }
+
+        for (location, &land) in self.map.iter_mut().zip(mask.map.iter()) {
+            if !matches!(location.terrain, Terrain::Land | Terrain::Water) {
+                continue;
+            }
+            location.terrain = if land { Terrain::Land } else { Terrain::Water };
+            if location
+                .piece
+                .as_ref()
+                .is_some_and(|piece| !piece.can_occupy(&location.terrain))
+            {
+                location.piece = None;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn from_ascii(s: &str) -> Result<Self, EmpyreError> {
+        let rows: Vec<Vec<char>> = s.lines().map(|row| row.chars().collect()).collect();
+        let width = rows.first().map_or(0, |row| row.len());
+        if width == 0 {
+            return Err(EmpyreError::InvalidMap("empty map".to_string()));
+        }
+
+        let mut map = Vec::with_capacity(width * rows.len());
+        for (y, row) in rows.iter().enumerate() {
+            if row.len() != width {
+                return Err(EmpyreError::RaggedMap {
+                    row: y,
+                    expected: width,
+                    found: row.len(),
+                });
+            }
+
+            for (x, c) in row.iter().enumerate() {
+                let pos = Position::new(x as i16, y as i16);
+                let location = match (Terrain::from_glyph(*c), Piece::from_glyph(*c)) {
+                    (Some(terrain), _) => Location::new(pos, terrain, 0),
+                    (None, Some(piece)) => Location {
+                        pos,
+                        terrain: Terrain::Land,
+                        height: 0,
+                        piece: Some(piece),
+                    },
+                    (None, None) => {
+                        return Err(EmpyreError::InvalidMap(format!(
+                            "unknown glyph {c:?} at ({x}, {y})"
+                        )))
+                    }
+                };
+                map.push(location);
+            }
+        }
+
+        Ok(Grid {
+            width: width as u16,
+            height: rows.len() as u16,
+            map,
+        })
+    }
+
+    // Roughens the shore by flipping about `strength` percent of the tiles
+    // that sit on it: land next to water and water next to land. Tiles
+    // holding a piece are left alone.
+    pub fn jitter_coastline(&mut self, strength: u16, rng: &mut impl Rng) {
+        let shore: Vec<Position> = self
+            .map
+            .iter()
+            .filter(|location| location.piece.is_none())
+            .filter(|location| {
+                let other = match location.terrain {
+                    Terrain::Land => Terrain::Water,
+                    Terrain::Water => Terrain::Land,
+                    _ => return false,
+                };
+                self.neighbours(location.pos).any(|n| n.terrain == other)
+            })
+            .map(|location| location.pos)
+            .collect();
+
+        for pos in shore {
+            if rng.gen_range(0..100) < strength {
+                self[pos].terrain = match self[pos].terrain {
+                    Terrain::Land => Terrain::Water,
+                    _ => Terrain::Land,
+                };
+            }
+        }
+    }
+
+    // One cellular-automaton step: land or water with fewer than
+    // `land_threshold` neighbours of its own kind flips to the other. Off the
+    // map counts as the tile's own kind, so edges and corners are judged like
+    // any other tile. Tiles holding a piece are left alone.
+    pub fn erode(&mut self, land_threshold: usize) {
+        let flipped: Vec<_> = self
+            .map
+            .iter()
+            .filter(|location| location.piece.is_none())
+            .filter(|location| {
+                let (mut same, mut inside) = (0, 0);
+                for n in self.neighbours(location.pos) {
+                    inside += 1;
+                    if n.terrain == location.terrain {
+                        same += 1;
+                    }
+                }
+                same + DIRS8.len() - inside < land_threshold
+            })
+            .map(|location| location.pos)
+            .collect();
+
+        for pos in flipped {
+            self[pos].terrain = match self[pos].terrain {
+                Terrain::Land => Terrain::Water,
+                Terrain::Water => Terrain::Land,
+                unchanged => unchanged,
+            };
+        }
+    }
+
+    // Runs `erode` for several passes to clean up speckle left by hand
+    // editing, e.g. after painting a coastline. Pieces and their tiles stay.
+    pub fn smooth_terrain(&mut self, passes: usize, land_threshold: usize) {
+        for _ in 0..passes {
+            self.erode(land_threshold);
+        }
+    }
+
+    // Groups of land tiles connected in any of the eight directions.
+    pub fn landmasses(&self) -> Vec<Vec<Position>> {
+        self.components(Terrain::Land)
+    }
+
+    // Groups of water tiles connected in any of the eight directions.
+    pub fn seas(&self) -> Vec<Vec<Position>> {
+        self.components(Terrain::Water)
+    }
+
+    // The flood fill works from a queue rather than recursing, so one huge
+    // ocean or continent cannot overflow the stack.
+    fn components(&self, terrain: Terrain) -> Vec<Vec<Position>> {
+        let mut seen = vec![false; self.map.len()];
+        let mut components = Vec::new();
+
+        for start in self.map.iter().filter(|l| l.terrain == terrain) {
+            if seen[pos_to_idx(start.pos, self.width)] {
+                continue;
+            }
+
+            let mut component = Vec::new();
+            let mut queue = VecDeque::from([start.pos]);
+            seen[pos_to_idx(start.pos, self.width)] = true;
+            while let Some(pos) = queue.pop_front() {
+                component.push(pos);
+                for n_pos in self.neighbour_positions(pos) {
+                    let idx = pos_to_idx(n_pos, self.width);
+                    if !seen[idx] && self[n_pos].terrain == terrain {
+                        seen[idx] = true;
+                        queue.push_back(n_pos);
+                    }
+                }
+            }
+            components.push(component);
+        }
+        components
+    }
+
+    // Land tiles whose loss would split their landmass in two, i.e. the
+    // articulation points of the 8-connected land graph, in row-major order.
+    // The depth-first search keeps its own stack so big continents cannot
+    // overflow the call stack.
+    pub fn chokepoints(&self) -> Vec<Position> {
+        let is_land = |pos: Position| self[pos].terrain == Terrain::Land;
+        let mut disc = vec![0; self.map.len()];
+        let mut low = vec![0; self.map.len()];
+        let mut cut = vec![false; self.map.len()];
+        let mut timer = 0;
+
+        for start in self.map.iter().filter(|l| l.terrain == Terrain::Land) {
+            let start_idx = pos_to_idx(start.pos, self.width);
+            if disc[start_idx] != 0 {
+                continue;
+            }
+
+            timer += 1;
+            disc[start_idx] = timer;
+            low[start_idx] = timer;
+            let mut root_children = 0;
+            let mut stack = vec![(
+                start.pos,
+                None,
+                self.neighbour_positions(start.pos).collect::<Vec<_>>(),
+            )];
+
+            while let Some((pos, parent, pending)) = stack.last_mut() {
+                let (pos, parent) = (*pos, *parent);
+                let idx = pos_to_idx(pos, self.width);
+                if let Some(n_pos) = pending.pop() {
+                    if !is_land(n_pos) || Some(n_pos) == parent {
+                        continue;
+                    }
+                    let n_idx = pos_to_idx(n_pos, self.width);
+                    if disc[n_idx] == 0 {
+                        timer += 1;
+                        disc[n_idx] = timer;
+                        low[n_idx] = timer;
+                        if idx == start_idx {
+                            root_children += 1;
+                        }
+                        stack.push((n_pos, Some(pos), self.neighbour_positions(n_pos).collect()));
+                    } else {
+                        low[idx] = low[idx].min(disc[n_idx]);
+                    }
+                } else {
+                    stack.pop();
+                    if let Some(parent) = parent {
+                        let p_idx = pos_to_idx(parent, self.width);
+                        low[p_idx] = low[p_idx].min(low[idx]);
+                        if p_idx != start_idx && low[idx] >= disc[p_idx] {
+                            cut[p_idx] = true;
+                        }
+                    }
+                }
+            }
+            cut[start_idx] = root_children > 1;
+        }
+
+        (0..self.map.len())
+            .filter(|idx| cut[*idx])
+            .map(|idx| idx_to_pos(idx, self.width))
+            .collect()
+    }
+
+    // Joins the biggest landmass to the next `max_bridges` biggest ones by
+    // turning the water between their closest coastal tiles into land.
+    pub fn connect_largest_continents(&mut self, max_bridges: usize) {
+        let mut landmasses = self.landmasses();
+        landmasses.sort_by_key(|landmass| Reverse(landmass.len()));
+
+        let mut landmasses = landmasses.into_iter();
+        let Some(mut connected) = landmasses.next() else {
+            return;
+        };
+        for other in landmasses.take(max_bridges) {
+            if let Some((mut pos, to, _)) = self.continent_gap(&connected, &other) {
+                while pos != to {
+                    pos = pos
+                        + Position {
+                            x: (to.x - pos.x).signum(),
+                            y: (to.y - pos.y).signum(),
+                        };
+                    if self[pos].terrain == Terrain::Water {
+                        self[pos].terrain = Terrain::Land;
+                        connected.push(pos);
+                    }
+                }
+            }
+            connected.extend(other);
+        }
+    }
+
+    // The closest pair of coastal tiles, one from each continent, and the
+    // distance between them. `None` if either continent has no coast.
+    pub fn continent_gap(
+        &self,
+        a: &[Position],
+        b: &[Position],
+    ) -> Option<(Position, Position, usize)> {
+        let coastal = |landmass: &[Position]| -> Vec<Position> {
+            landmass
+                .iter()
+                .copied()
+                .filter(|pos| self.neighbours(*pos).any(|n| n.terrain == Terrain::Water))
+                .collect()
+        };
+
+        let theirs = coastal(b);
+        coastal(a)
+            .into_iter()
+            .flat_map(|from| theirs.iter().map(move |to| (from, *to, from.distance(to))))
+            .min_by_key(|(_, _, distance)| *distance)
+    }
+
+    // Steps from each tile to the nearest water, moving in all eight
+    // directions. Water is 0; without any water every tile is u16::MAX.
+    pub fn distance_to_water(&self) -> Grid<u16> {
+        let mut distances = Grid {
+            width: self.width,
+            height: self.height,
+            map: vec![u16::MAX; self.map.len()],
+        };
+        let mut queue = VecDeque::new();
+        for location in self.map.iter().filter(|l| l.terrain == Terrain::Water) {
+            distances[location.pos] = 0;
+            queue.push_back(location.pos);
+        }
+
+        while let Some(pos) = queue.pop_front() {
+            let next = distances[pos] + 1;
+            for n_pos in self.neighbour_positions(pos) {
+                if distances[n_pos] > next {
+                    distances[n_pos] = next;
+                    queue.push_back(n_pos);
+                }
+            }
+        }
+        distances
+    }
+
+    // The most steps a shortest walk within `continent` can take, found by
+    // searching from any tile to the farthest one and again from there.
+    // Exact on tree-like continents and a close lower bound otherwise.
+    // Positions off the map are ignored.
+    pub fn continent_diameter(&self, continent: &[Position]) -> usize {
+        let continent: Vec<Position> = continent
+            .iter()
+            .copied()
+            .filter(|pos| self.covers(*pos))
+            .collect();
+        let mut inside = vec![false; self.map.len()];
+        for pos in &continent {
+            inside[pos_to_idx(*pos, self.width)] = true;
+        }
+
+        let farthest = |start: Position| -> (Position, usize) {
+            let mut steps = vec![usize::MAX; self.map.len()];
+            steps[pos_to_idx(start, self.width)] = 0;
+            let mut queue = VecDeque::from([start]);
+            let mut last = (start, 0);
+            while let Some(pos) = queue.pop_front() {
+                let here = steps[pos_to_idx(pos, self.width)];
+                last = (pos, here);
+                for n_pos in self.neighbour_positions(pos) {
+                    let idx = pos_to_idx(n_pos, self.width);
+                    if inside[idx] && steps[idx] == usize::MAX {
+                        steps[idx] = here + 1;
+                        queue.push_back(n_pos);
+                    }
+                }
+            }
+            last
+        };
+
+        match continent.first() {
+            Some(start) => farthest(farthest(*start).0).1,
+            None => 0,
+        }
+    }
+
+    // Twice the greatest number of steps from any tile of `continent` to the
+    // nearest tile outside it. That makes the score a corridor's width
+    // rounded up to an even number: one- and two-tile corridors both score
+    // 2, three- and four-tile ones 4. Water, mountains and the map edge all
+    // bound the continent, and positions off the map are ignored.
+    pub fn max_corridor_width(&self, continent: &[Position]) -> u16 {
+        let continent: Vec<Position> = continent
+            .iter()
+            .copied()
+            .filter(|pos| self.covers(*pos))
+            .collect();
+        let mut inside = vec![false; self.map.len()];
+        for pos in &continent {
+            inside[pos_to_idx(*pos, self.width)] = true;
+        }
+
+        let mut distances = vec![u16::MAX; self.map.len()];
+        let mut queue = VecDeque::new();
+        for pos in &continent {
+            let on_edge = self.neighbour_positions(*pos).count() < DIRS8.len();
+            if on_edge
+                || self
+                    .neighbour_positions(*pos)
+                    .any(|n_pos| !inside[pos_to_idx(n_pos, self.width)])
+            {
+                distances[pos_to_idx(*pos, self.width)] = 1;
+                queue.push_back(*pos);
+            }
+        }
+
+        let mut widest = 0;
+        while let Some(pos) = queue.pop_front() {
+            let here = distances[pos_to_idx(pos, self.width)];
+            widest = widest.max(here);
+            for n_pos in self.neighbour_positions(pos) {
+                let idx = pos_to_idx(n_pos, self.width);
+                if inside[idx] && distances[idx] > here + 1 {
+                    distances[idx] = here + 1;
+                    queue.push_back(n_pos);
+                }
+            }
+        }
+        2 * widest
+    }
+
+    pub fn find_path(
+        &self,
+        from: Position,
+        to: Position,
+        passable: impl Fn(&Location) -> bool,
+    ) -> Option<Vec<Position>> {
+        self.find_path_with(from, to, Movement::default(), passable)
+    }
+
+    // A shortest route from `from` to `to`, both included, that only steps
+    // onto tiles `passable` accepts. The start tile itself need not be
+    // passable. This is A* with the Chebyshev distance as the estimate, or
+    // the Manhattan distance under 4-connectivity, neither of which ever
+    // overshoots when every step costs one. Without `cut_corners` a diagonal
+    // step is refused when both tiles beside it are impassable.
+    //
+    // Among equally short routes the choice is deterministic: open tiles
+    // with the same estimate are expanded fewest steps first, then lowest
+    // row-major index first, and a tile keeps the first predecessor that
+    // reached it at its best cost, with neighbours tried in reading order.
+    pub fn find_path_with(
+        &self,
+        from: Position,
+        to: Position,
+        movement: Movement,
+        passable: impl Fn(&Location) -> bool,
+    ) -> Option<Vec<Position>> {
+        if !self.covers(from) || !self.covers(to) {
+            return None;
+        }
+
+        let squeezed = |pos: Position, n_pos: Position| {
+            !movement.cut_corners
+                && !passable(
+                    &self[Position {
+                        x: n_pos.x,
+                        y: pos.y,
+                    }],
+                )
+                && !passable(
+                    &self[Position {
+                        x: pos.x,
+                        y: n_pos.y,
+                    }],
+                )
+        };
+        let estimate = |pos: Position| match movement.connectivity {
+            Connectivity::Four => (pos.x.abs_diff(to.x) + pos.y.abs_diff(to.y)) as u32,
+            Connectivity::Eight => pos.chebyshev(&to) as u32,
+        };
+        let mut cost = vec![u32::MAX; self.map.len()];
+        let mut came_from: Vec<Option<Position>> = vec![None; self.map.len()];
+        let mut open =
+            BinaryHeap::from([Reverse((estimate(from), 0, pos_to_idx(from, self.width)))]);
+        cost[pos_to_idx(from, self.width)] = 0;
+
+        while let Some(Reverse((_, steps, idx))) = open.pop() {
+            if steps > cost[idx] {
+                continue;
+            }
+            let pos = idx_to_pos(idx, self.width);
+            if pos == to {
+                let mut path = vec![to];
+                let mut step = to;
+                while step != from {
+                    step = came_from[pos_to_idx(step, self.width)]?;
+                    path.push(step);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for n_pos in self.neighbour_positions_with(pos, movement.connectivity) {
+                let n_idx = pos_to_idx(n_pos, self.width);
+                if steps + 1 < cost[n_idx] && passable(&self[n_pos]) && !squeezed(pos, n_pos) {
+                    cost[n_idx] = steps + 1;
+                    came_from[n_idx] = Some(pos);
+                    open.push(Reverse((steps + 1 + estimate(n_pos), steps + 1, n_idx)));
+                }
+            }
+        }
+        None
+    }
+
+    // The shortest route by sea between two coastal tiles, which may be on
+    // land, or `None` when no stretch of water joins them. Every tile between
+    // the two ends is water, so neighbouring coastal cities are never joined
+    // by a step straight over land.
+    pub fn shortest_sea_route(
+        &self,
+        from_coast: Position,
+        to_coast: Position,
+    ) -> Option<Vec<Position>> {
+        let water = |location: &Location| location.terrain == Terrain::Water;
+        if !self.covers(to_coast) {
+            return None;
+        }
+        if from_coast == to_coast || water(&self[to_coast]) {
+            return self.find_path(from_coast, to_coast, water);
+        }
+
+        self.neighbour_positions(to_coast)
+            .filter(|n_pos| water(&self[*n_pos]))
+            .filter_map(|landing| self.find_path(from_coast, landing, water))
+            .min_by_key(|route| route.len())
+            .map(|mut route| {
+                route.push(to_coast);
+                route
+            })
+    }
+
+    // The rounded mean position of each landmass, in `landmasses` order. Good
+    // for labels, though on a crescent it may fall in the sea.
+    pub fn continent_centroids(&self) -> Vec<Position> {
+        self.landmasses()
+            .iter()
+            .map(|landmass| {
+                let n = landmass.len() as f32;
+                let (sx, sy) = landmass.iter().fold((0.0, 0.0), |(sx, sy), pos| {
+                    (sx + pos.x as f32, sy + pos.y as f32)
+                });
+                Position {
+                    x: (sx / n).round() as i16,
+                    y: (sy / n).round() as i16,
+                }
+            })
+            .collect()
+    }
+
+    // The pole of inaccessibility: the tile of `continent` farthest from water.
+    // Ties go to the tile that comes first in `continent`.
+    pub fn continent_center(&self, continent: &[Position]) -> Option<Position> {
+        let distances = self.distance_to_water();
+        continent
+            .iter()
+            .copied()
+            .rev()
+            .max_by_key(|pos| distances[*pos])
+    }
+
+    // Drops every all-water row and column at the edges of the map. The
+    // result is renumbered from (0, 0), so positions shift up and left by
+    // the size of the trimmed margins. An all-water map trims to 0 x 0.
+    pub fn trim_water_border(&self) -> Grid<Location> {
+        self.trim_water_border_with_offset().0
+    }
+
+    // Like `trim_water_border`, but also returns the offset for
+    // `translate_positions`.
+    pub fn trim_water_border_with_offset(&self) -> (Grid<Location>, Position) {
+        let content: Vec<Position> = self
+            .map
+            .iter()
+            .filter(|location| location.terrain != Terrain::Water)
+            .map(|location| location.pos)
+            .collect();
+        let (Some(min_x), Some(max_x)) = (
+            content.iter().map(|pos| pos.x).min(),
+            content.iter().map(|pos| pos.x).max(),
+        ) else {
+            let empty = Grid {
+                width: 0,
+                height: 0,
+                map: Vec::new(),
+            };
+            return (empty, Position { x: 0, y: 0 });
+        };
+        let min_y = content.iter().map(|pos| pos.y).min().unwrap();
+        let max_y = content.iter().map(|pos| pos.y).max().unwrap();
+
+        let top_left = Position { x: min_x, y: min_y };
+        self.crop_with_offset(
+            top_left,
+            (max_x - min_x + 1) as u16,
+            (max_y - min_y + 1) as u16,
+        )
+        .expect("content bounds lie within the map")
+    }
+
+    // The fraction of tiles whose terrain matches their mirror image, from
+    // 0.0 up to 1.0 for a perfectly symmetric map.
+    pub fn symmetry_score(&self, axis: SymmetryAxis) -> f32 {
+        if self.map.is_empty() {
+            return 1.0;
+        }
+        let matching = self
+            .map
+            .iter()
+            .filter(|location| location.terrain == self[self.mirror(location.pos, axis)].terrain)
+            .count();
+        matching as f32 / self.map.len() as f32
+    }
+
+    pub fn same_terrain(&self, other: &Grid<Location>) -> bool {
+        self.width == other.width
+            && self.height == other.height
+            && self
+                .map
+                .iter()
+                .zip(other.map.iter())
+                .all(|(a, b)| a.terrain == b.terrain)
+    }
+
+    // A stable 64-bit FNV-1a hash of the dimensions, terrain and pieces, so
+    // the same map gets the same id on every run and platform.
+    pub fn fingerprint(&self) -> u64 {
+        const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+        const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+        let mut bytes = Vec::with_capacity(4 + self.map.len() * 2);
+        bytes.extend(self.width.to_le_bytes());
+        bytes.extend(self.height.to_le_bytes());
+        for location in &self.map {
+            bytes.extend(location.terrain.to_bytes());
+            match &location.piece {
+                Some(piece) => bytes.extend(piece.to_string().bytes()),
+                None => bytes.push(0),
+            }
+        }
+        bytes.iter().fold(FNV_OFFSET, |hash, &b| {
+            (hash ^ b as u64).wrapping_mul(FNV_PRIME)
+        })
+    }
+
+    // Like `write_to`, but with the terrain glyphs taken from `style`. Every
+    // row is written out in full, including any trailing unknown tiles.
+    pub fn write_styled(&self, w: &mut impl Write, style: &TerrainStyle) -> io::Result<()> {
+        let theme = Theme {
+            terrain: *style,
+            ..Theme::default()
+        };
+        self.write_themed(w, &theme)
+    }
+
+    pub fn write_themed(&self, w: &mut impl Write, theme: &Theme) -> io::Result<()> {
+        for pos in self.positions_in(Position { x: 0, y: 0 }, self.width, self.height) {
+            match &self[pos].piece {
+                Some(piece) => write!(w, "{}", theme.piece_glyph(piece))?,
+                None => write!(w, "{}", self[pos].terrain.glyph(&theme.terrain))?,
+            }
+            if pos.x == self.width as i16 - 1 {
+                writeln!(w)?
+            }
+        }
+        Ok(())
+    }
+
+    // Counts land/water edges: each land tile adds one for every orthogonal
+    // neighbour that is water. The map border is not coastline.
+    pub fn coastline_length(&self) -> usize {
+        self.map
+            .iter()
+            .filter(|location| location.terrain == Terrain::Land)
+            .map(|location| {
+                self.neighbours_with(location.pos, Connectivity::Four)
+                    .filter(|n| n.terrain == Terrain::Water)
+                    .count()
+            })
+            .sum()
+    }
+
+    // Tiles an observer can see. Height lets the observer see farther, one
+    // extra tile per SIGHT_HEIGHT_STEP, and a tile is hidden when something
+    // between the two rises above the straight sight line joining them. An
+    // observer off the map sees nothing.
+    pub fn visible_from(&self, observer: Position, base_radius: u16) -> Vec<Position> {
+        if !self.covers(observer) {
+            return Vec::new();
+        }
+        let eye = self[observer].height as i64;
+        let radius = base_radius.saturating_add(self[observer].height / SIGHT_HEIGHT_STEP);
+
+        self.affected_tiles(observer, radius)
+            .into_iter()
+            .filter(|target| observer.distance(target) <= radius as usize)
+            .filter(|target| {
+                let line = line_between(observer, *target);
+                let steps = (line.len() - 1) as i64;
+                let end = self[*target].height as i64;
+                (1..line.len().saturating_sub(1)).all(|step| {
+                    let sight = eye + (end - eye) * step as i64 / steps;
+                    self[line[step]].height as i64 <= sight
+                })
+            })
+            .collect()
+    }
+
+    // Roughly how many turns a land scout starting at `start` needs to see
+    // every tile of its landmass, moving `movement` tiles a turn and always
+    // heading for the nearest tile it has not seen yet.
+    pub fn explore_turns(&self, start: Position, sight_radius: u16, movement: u32) -> u32 {
+        if !self.covers(start) || self[start].terrain != Terrain::Land || movement == 0 {
+            return 0;
+        }
+        let land = |location: &Location| location.terrain == Terrain::Land;
+
+        // Everything on the scout's landmass starts out unseen.
+        let mut unseen = vec![false; self.map.len()];
+        let mut queue = VecDeque::from([start]);
+        unseen[pos_to_idx(start, self.width)] = true;
+        let mut remaining = 1;
+        while let Some(pos) = queue.pop_front() {
+            for n_pos in self.neighbour_positions(pos) {
+                let idx = pos_to_idx(n_pos, self.width);
+                if !unseen[idx] && land(&self[n_pos]) {
+                    unseen[idx] = true;
+                    remaining += 1;
+                    queue.push_back(n_pos);
+                }
+            }
+        }
+
+        let mut scout = start;
+        let mut moves = 0u32;
+        loop {
+            for seen in self.visible_from(scout, sight_radius) {
+                let idx = pos_to_idx(seen, self.width);
+                if unseen[idx] {
+                    unseen[idx] = false;
+                    remaining -= 1;
+                }
+            }
+            if remaining == 0 {
+                return moves.div_ceil(movement);
+            }
+
+            // Breadth-first over land to the nearest unseen tile, then one
+            // step along the way there.
+            let mut came_from: Vec<Option<Position>> = vec![None; self.map.len()];
+            let mut queue = VecDeque::from([scout]);
+            came_from[pos_to_idx(scout, self.width)] = Some(scout);
+            let mut step = None;
+            while let Some(pos) = queue.pop_front() {
+                if unseen[pos_to_idx(pos, self.width)] {
+                    let mut back = pos;
+                    while let Some(prev) = came_from[pos_to_idx(back, self.width)] {
+                        if prev == scout {
+                            break;
+                        }
+                        back = prev;
+                    }
+                    step = Some(back);
+                    break;
+                }
+                for n_pos in self.neighbour_positions(pos) {
+                    let idx = pos_to_idx(n_pos, self.width);
+                    if came_from[idx].is_none() && land(&self[n_pos]) {
+                        came_from[idx] = Some(pos);
+                        queue.push_back(n_pos);
+                    }
+                }
+            }
+
+            scout = step.expect("unseen tiles lie on the scout's landmass");
+            moves += 1;
+        }
+    }
+
+    // Collects every problem with a map, e.g. one loaded from a hand-edited
+    // file: wrong data length, stale positions, and pieces on terrain they
+    // cannot occupy (pieces never belong on Unknown).
+    pub fn validate(&self) -> Result<(), Vec<EmpyreError>> {
+        let mut errors = Vec::new();
+
+        let expected = self.width as usize * self.height as usize;
+        if self.map.len() != expected {
+            errors.push(EmpyreError::SizeMismatch {
+                expected,
+                found: self.map.len(),
+            });
+        }
+
+        for (idx, location) in self.map.iter().enumerate() {
+            if location.pos != idx_to_pos(idx, self.width) {
+                errors.push(EmpyreError::InvalidMap(format!(
+                    "tile {idx} claims to be at {:?}",
+                    location.pos
+                )));
+            }
+            if let Some(piece) = &location.piece {
+                if !piece.can_occupy(&location.terrain) {
+                    errors.push(EmpyreError::MisplacedPiece {
+                        pos: location.pos,
+                        terrain: location.terrain,
+                    });
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    pub fn pieces(&self) -> impl Iterator<Item = (Position, &Piece)> {
+        self.map
+            .iter()
+            .filter_map(|location| location.piece.as_ref().map(|piece| (location.pos, piece)))
+    }
+
+    pub fn nearest_piece(
+        &self,
+        from: Position,
+        pred: impl Fn(&Piece) -> bool,
+    ) -> Option<(Position, &Piece)> {
+        self.pieces()
+            .filter(|(_, piece)| pred(piece))
+            .min_by_key(|(pos, _)| from.distance(pos))
+    }
+
+    pub fn cities_by_distance(&self, from: Position) -> Vec<(Position, &Piece)> {
+        let mut cities: Vec<_> = self
+            .pieces()
+            .filter(|(_, piece)| **piece == Piece::City)
+            .collect();
+        cities.sort_by_key(|(pos, _)| from.distance(pos));
+        cities
+    }
+
+    pub fn free_adjacent(&self, pos: Position, piece: &Piece) -> Option<Position> {
+        self.neighbour_positions(pos).find(|n_pos| {
+            let location = &self[*n_pos];
+            location.piece.is_none() && piece.can_occupy(&location.terrain)
+        })
+    }
+
+    // Free land tiles next to a transport at `transport_pos`, where its
+    // cargo could be put ashore.
+    pub fn landing_tiles(&self, transport_pos: Position) -> Vec<Position> {
+        self.neighbour_positions(transport_pos)
+            .filter(|n_pos| {
+                let location = &self[*n_pos];
+                location.terrain == Terrain::Land && location.piece.is_none()
+            })
+            .collect()
+    }
+
+    // The pieces a bombardment of `radius` around `center` would hit. Pieces
+    // have no hit points yet, so applying the damage is up to the caller.
+    pub fn bombard_targets(&self, center: Position, radius: u16) -> Vec<(Position, &Piece)> {
+        self.affected_tiles(center, radius)
+            .into_iter()
+            .filter_map(|pos| self[pos].piece.as_ref().map(|piece| (pos, piece)))
+            .collect()
+    }
+
+    // Unchecked, for callers that have already made sure the piece fits.
+    // Outside the crate `place_piece_relocating` is the checked way in.
+    pub(crate) fn put_piece(&mut self, piece: Piece, pos: Position) {
+        self[pos].piece = Some(piece);
+    }
+
+    // Puts `piece` on the nearest tile to `pos` that it can occupy and that
+    // is free, returning where it ended up.
+    pub fn place_piece_relocating(
+        &mut self,
+        piece: Piece,
+        pos: Position,
+    ) -> Result<Position, EmpyreError> {
+        if !self.covers(pos) {
+            return Err(EmpyreError::OutOfBounds(pos));
+        }
+        let target = self
+            .spiral_from(pos)
+            .find(|s_pos| self[*s_pos].piece.is_none() && piece.can_occupy(&self[*s_pos].terrain))
+            .ok_or(EmpyreError::NoFreeTile(pos))?;
+        self.put_piece(piece, target);
+        Ok(target)
+    }
+
+    pub(crate) fn remove_piece(&mut self, pos: Position) {
+        self[pos].piece = None;
+    }
+
+    // Changes one tile's terrain. A piece on the tile stays put, so the
+    // change is refused if the piece could not stand on the new terrain.
+    pub fn set_terrain(&mut self, pos: Position, terrain: Terrain) -> Result<(), EmpyreError> {
+        if !self.covers(pos) {
+            return Err(EmpyreError::OutOfBounds(pos));
+        }
+        if let Some(piece) = &self[pos].piece {
+            if !piece.can_occupy(&terrain) {
+                return Err(EmpyreError::MisplacedPiece { pos, terrain });
+            }
+        }
+        self[pos].terrain = terrain;
+        Ok(())
+    }
+
+    // Rings the map with `thickness` tiles of impassable mountain.
+    pub fn wall_border(&mut self, thickness: u16) {
+        let t = thickness as i16;
+        let (right, bottom) = (self.width as i16 - 1, self.height as i16 - 1);
+        for location in self.map.iter_mut() {
+            let Position { x, y } = location.pos;
+            if x.min(y).min(right - x).min(bottom - y) < t {
+                location.terrain = Terrain::Mountain;
+                location.piece = None;
+            }
+        }
+    }
+
+    // Sets every tile in the block to `terrain`, removing any piece that
+    // cannot stay on it.
+    pub fn fill_terrain(&mut self, top_left: Position, w: u16, h: u16, terrain: Terrain) {
+        for pos in self.positions_in(top_left, w, h).collect::<Vec<_>>() {
+            let location = &mut self[pos];
+            location.terrain = terrain;
+            if location
+                .piece
+                .as_ref()
+                .is_some_and(|piece| !piece.can_occupy(&terrain))
+            {
+                location.piece = None;
+            }
+        }
+    }
+}
+
+impl<T> Index<Position> for Grid<T> {
+    type Output = T;
+
+    fn index(&self, index: Position) -> &Self::Output {
+        &self.map[pos_to_idx(index, self.width)]
+    }
+}
+
+impl<T> IndexMut<Position> for Grid<T> {
+    fn index_mut(&mut self, index: Position) -> &mut Self::Output {
+        &mut self.map[pos_to_idx(index, self.width)]
+    }
+}
+
+impl<T> Grid<T>
+where
+    T: Display,
+{
+    // Streams the same output as `Display` without building it in memory first.
+    pub fn write_to(&self, w: &mut impl Write) -> io::Result<()> {
+        for pos in self.positions_in(Position { x: 0, y: 0 }, self.width, self.height) {
+            write!(w, "{}", self[pos])?;
+            if pos.x == self.width as i16 - 1 {
+                writeln!(w)?
+            }
+        }
+        Ok(())
+    }
+
+    // The `Display` output one row at a time, without the line endings.
+    pub fn rows(&self) -> impl Iterator<Item = String> + '_ {
+        (0..self.height as i16).map(move |y| {
+            self.positions_in(Position { x: 0, y }, self.width, 1)
+                .map(|pos| self[pos].to_string())
+                .collect()
+        })
+    }
+}
+
+impl<T> Display for Grid<T>
+where
+    T: Display,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for pos in self.positions_in(Position { x: 0, y: 0 }, self.width, self.height) {
+            write!(f, "{}", self[pos])?;
+            if pos.x == self.width as i16 - 1 {
+                writeln!(f)?
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct NeighbourIter<'g, T> {
+    grid: &'g Grid<T>,
+    pos: Position,
+    dirs: &'static [Position],
+    idx: usize,
+}
+
+impl<'g, T> Iterator for NeighbourIter<'g, T> {
+    type Item = &'g T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.idx >= self.dirs.len() {
+            return None;
+        }
+
+        loop {
+            let n_pos = self.pos + self.dirs[self.idx];
+            self.idx += 1;
+
+            if self.grid.covers(n_pos) {
+                return Some(&self.grid[n_pos]);
+            }
+
+            if self.idx >= self.dirs.len() {
+                return None;
+            }
+        }
+    }
+}
+
+// Bresenham's line from `from` to `to`, including both ends.
+fn line_between(from: Position, to: Position) -> Vec<Position> {
+    let (dx, dy) = ((to.x - from.x).abs(), -(to.y - from.y).abs());
+    let (sx, sy) = ((to.x - from.x).signum(), (to.y - from.y).signum());
+    let mut err = dx + dy;
+    let mut pos = from;
+    let mut line = vec![pos];
+
+    while pos != to {
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            pos.x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            pos.y += sy;
+        }
+        line.push(pos);
+    }
+    line
+}
+
+// Moves every position by `offset`, e.g. the one returned by
+// `crop_with_offset`, to rebase positions kept outside the grid.
+pub fn translate_positions(positions: &[Position], offset: Position) -> Vec<Position> {
+    positions.iter().map(|pos| *pos + offset).collect()
 }
 
-impl<T> IndexMut<Position> for Grid<T> {
-    fn index_mut(&mut self, index: Position) -> &mut Self::Output {
-        &mut self.map[pos_to_idx(index, self.width)]
+fn classify(level: u16, water_height: u16) -> Terrain {
+    if level <= water_height {
+        Terrain::Water
+    } else {
+        Terrain::Land
+    }
+}
+
+fn pos_to_idx(pos: Position, width: u16) -> usize {
+    pos.y as usize * width as usize + pos.x as usize
+}
+
+fn idx_to_pos(idx: usize, width: u16) -> Position {
+    Position {
+        x: (idx % width as usize) as i16,
+        y: (idx / width as usize) as i16,
+    }
+}
+
+// See https://en.wikipedia.org/wiki/Integer_square_root
+fn isqrt(val: usize) -> usize {
+    let mut left = 0;
+    let mut right = val + 1;
+
+    while left != right - 1 {
+        let mid = (left + right) / 2;
+
+        if mid * mid <= val {
+            left = mid;
+        } else {
+            right = mid;
+        }
+    }
+    left
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_grid() {
+        assert_eq!(
+            Grid::new(10, 20),
+            Grid::<u16> {
+                width: 10,
+                height: 20,
+                map: vec![0; 200]
+            }
+        );
+    }
+
+    #[test]
+    fn test_grid_from_vec() {
+        let grid = Grid::from_vec(3, 2, vec![1, 2, 3, 4, 5, 6]).unwrap();
+        assert_eq!(grid[Position { x: 0, y: 1 }], 4);
+    }
+
+    #[test]
+    fn test_grid_from_vec_wrong_length() {
+        assert!(matches!(
+            Grid::from_vec(3, 2, vec![1, 2, 3]),
+            Err(EmpyreError::SizeMismatch {
+                expected: 6,
+                found: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn test_linear_indices() {
+        let grid = Grid::from_vec(3, 2, vec![10, 11, 12, 13, 14, 15]).unwrap();
+        assert_eq!(grid.index_linear(4), Some(&14));
+        assert_eq!(grid.position_of(4), Some(Position { x: 1, y: 1 }));
+        assert_eq!(grid[grid.position_of(5).unwrap()], 15);
+
+        assert_eq!(grid.index_linear(6), None);
+        assert_eq!(grid.position_of(6), None);
+    }
+
+    #[test]
+    fn test_stamp() {
+        let mut grid = Grid::<u16>::new(10, 10);
+        let block = Grid::from_vec(3, 3, vec![1; 9]).unwrap();
+        grid.stamp(&block, Position { x: 2, y: 4 }).unwrap();
+
+        assert_eq!(grid.map.iter().filter(|level| **level == 1).count(), 9);
+        for pos in grid.positions_in(Position { x: 2, y: 4 }, 3, 3) {
+            assert_eq!(grid[pos], 1);
+        }
+    }
+
+    #[test]
+    fn test_stamp_map() {
+        let mut map = Grid::from_ascii("....\n....\n....\n").unwrap();
+        let prefab = Grid::from_ascii("+O\n").unwrap();
+        map.stamp(&prefab, Position { x: 2, y: 1 }).unwrap();
+
+        assert_eq!(map.to_string(), "....\n..+O\n....\n");
+        assert!(map.validate().is_ok());
+        let cities: Vec<Position> = map.pieces().map(|(pos, _)| pos).collect();
+        assert_eq!(cities, vec![Position { x: 3, y: 1 }]);
+    }
+
+    #[test]
+    fn test_stamp_overflow() {
+        let mut grid = Grid::<u16>::new(10, 10);
+        let block = Grid::from_vec(3, 3, vec![1; 9]).unwrap();
+
+        assert!(matches!(
+            grid.stamp(&block, Position { x: 8, y: 4 }),
+            Err(EmpyreError::OutOfBounds(_))
+        ));
+        assert!(grid.map.iter().all(|level| *level == 0));
+
+        assert!(matches!(
+            grid.stamp(&block, Position { x: -1, y: 4 }),
+            Err(EmpyreError::OutOfBounds(Position { x: -1, y: 4 }))
+        ));
+        assert!(matches!(
+            grid.stamp(&block, Position { x: 8, y: 4 }),
+            Err(EmpyreError::OutOfBounds(Position { x: 10, y: 6 }))
+        ));
+    }
+
+    #[test]
+    fn test_stamp_empty() {
+        let mut grid = Grid::<u16>::new(3, 3);
+        let empty = Grid::<u16>::new(0, 2);
+        assert!(grid.stamp(&empty, Position { x: 1, y: 1 }).is_ok());
+        assert!(grid.stamp(&empty, Position { x: 9, y: 9 }).is_ok());
+        assert_eq!(grid, Grid::<u16>::new(3, 3));
+    }
+
+    #[test]
+    fn test_new_random_size() {
+        let grid = Grid::new_random(37, 23);
+        assert_eq!(grid.map.len(), 37 * 23);
+    }
+
+    #[test]
+    fn test_transpose() {
+        let grid = Grid::from_vec(3, 2, vec![1, 2, 3, 4, 5, 6]).unwrap();
+        let transposed = grid.transpose();
+
+        assert_eq!((transposed.width, transposed.height), (2, 3));
+        assert_eq!(transposed.map, vec![1, 4, 2, 5, 3, 6]);
+        assert_eq!(
+            transposed[Position { x: 1, y: 2 }],
+            grid[Position { x: 2, y: 1 }]
+        );
+        assert_eq!(transposed.transpose(), grid);
+    }
+
+    #[test]
+    fn test_transpose_map() {
+        let map = Grid::from_ascii("+++\n...\n").unwrap();
+        let transposed = map.transpose();
+
+        assert_eq!(transposed.to_string(), "+.\n+.\n+.\n");
+        assert!(transposed.validate().is_ok());
+        assert_eq!(transposed.landmasses().len(), 1);
+        assert_eq!(transposed.transpose(), map);
+    }
+
+    #[test]
+    fn test_crop() {
+        let grid = Grid::from_vec(4, 3, (0..12).collect()).unwrap();
+        let cropped = grid.crop(Position { x: 1, y: 1 }, 2, 2).unwrap();
+
+        assert_eq!((cropped.width, cropped.height), (2, 2));
+        assert_eq!(cropped.map, vec![5, 6, 9, 10]);
+        assert!(matches!(
+            grid.crop(Position { x: 3, y: 1 }, 2, 2),
+            Err(EmpyreError::OutOfBounds(Position { x: 4, y: 2 }))
+        ));
+        assert!(matches!(
+            grid.crop(Position { x: 0, y: -2 }, 2, 2),
+            Err(EmpyreError::OutOfBounds(Position { x: 0, y: -2 }))
+        ));
+
+        let empty = grid.crop(Position { x: 2, y: 1 }, 0, 2).unwrap();
+        assert_eq!((empty.width, empty.height), (0, 2));
+        assert!(empty.map.is_empty());
+    }
+
+    #[test]
+    fn test_grid_index() {
+        let mut grid = Grid::<i32>::new(DEFAULT_MAP_WIDTH, DEFAULT_MAP_HEIGHT);
+        let pos = Position { x: 5, y: 5 };
+        grid[pos] = 1;
+        assert!(grid[pos] == 1);
+    }
+
+    #[test]
+    fn test_add_position() {
+        let p1 = Position { x: 1, y: 2 };
+        let p2 = Position { x: -1, y: 3 };
+        assert_eq!(p1 + p2, Position { x: 0, y: 5 })
+    }
+
+    #[test]
+    fn test_position_bytes() {
+        for pos in [
+            Position { x: 0, y: 0 },
+            Position { x: 99, y: 59 },
+            Position { x: -1, y: -300 },
+            Position {
+                x: i16::MIN,
+                y: i16::MAX,
+            },
+        ] {
+            assert_eq!(Position::from_bytes(pos.to_bytes()), pos);
+        }
+        assert_eq!(Position { x: -1, y: 2 }.to_bytes(), [0xff, 0xff, 2, 0]);
+    }
+
+    #[test]
+    fn test_terrain_bytes() {
+        for terrain in [
+            Terrain::Water,
+            Terrain::Land,
+            Terrain::Unknown,
+            Terrain::Mountain,
+        ] {
+            assert_eq!(Terrain::from_bytes(terrain.to_bytes()).unwrap(), terrain);
+        }
+        assert!(matches!(
+            Terrain::from_bytes([42]),
+            Err(EmpyreError::InvalidMap(_))
+        ));
+    }
+
+    #[test]
+    fn test_distance_weighted() {
+        let origin = Position { x: 0, y: 0 };
+        assert_eq!(origin.distance_weighted(&Position { x: 0, y: 3 }, 1, 2), 6);
+        assert_eq!(origin.distance_weighted(&Position { x: 3, y: 0 }, 1, 2), 3);
+        assert_eq!(
+            origin.distance_weighted(&Position { x: 3, y: 4 }, 1, 1),
+            origin.distance(&Position { x: 3, y: 4 })
+        );
+    }
+
+    #[test]
+    fn test_sub_position() {
+        let p1 = Position { x: 1, y: 2 };
+        let p2 = Position { x: -1, y: 3 };
+        assert_eq!(p1 - p2, Position { x: 2, y: -1 })
+    }
+
+    #[test]
+    fn test_neighbours() {
+        let mut grid = Grid::<u16>::new(10, 10);
+        grid[Position { x: 4, y: 4 }] = 1;
+        grid[Position { x: 5, y: 4 }] = 2;
+        grid[Position { x: 6, y: 4 }] = 3;
+        grid[Position { x: 4, y: 5 }] = 4;
+        grid[Position { x: 5, y: 5 }] = 5;
+        grid[Position { x: 6, y: 5 }] = 6;
+        grid[Position { x: 4, y: 6 }] = 7;
+        grid[Position { x: 5, y: 6 }] = 8;
+        grid[Position { x: 6, y: 6 }] = 9;
+
+        let mut nbrs = grid.neighbours(Position { x: 5, y: 5 });
+        assert_eq!(nbrs.next(), Some(&1));
+        assert_eq!(nbrs.next(), Some(&2));
+        assert_eq!(nbrs.next(), Some(&3));
+        assert_eq!(nbrs.next(), Some(&4));
+        assert_eq!(nbrs.next(), Some(&6));
+        assert_eq!(nbrs.next(), Some(&7));
+        assert_eq!(nbrs.next(), Some(&8));
+        assert_eq!(nbrs.next(), Some(&9));
+        assert_eq!(nbrs.next(), None);
+    }
+
+    #[test]
+    fn test_neighbours_match_positions() {
+        let grid = Grid::from_vec(4, 3, (0..12).collect()).unwrap();
+        for pos in grid.positions_in(Position { x: 0, y: 0 }, 4, 3) {
+            for connectivity in [Connectivity::Four, Connectivity::Eight] {
+                let values: Vec<_> = grid.neighbours_with(pos, connectivity).copied().collect();
+                let expected: Vec<_> = grid
+                    .neighbour_positions_with(pos, connectivity)
+                    .map(|n_pos| grid[n_pos])
+                    .collect();
+                assert_eq!(values, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_block3() {
+        let grid = Grid::from_vec(4, 3, (0..12u16).collect()).unwrap();
+        let block: Vec<_> = grid.block3(Position { x: 1, y: 1 }).collect();
+        assert_eq!(block.len(), 9);
+        assert!(block.contains(&(Position { x: 1, y: 1 }, &5)));
+        assert_eq!(
+            block.iter().map(|(_, v)| **v).collect::<Vec<_>>(),
+            vec![0, 1, 2, 4, 5, 6, 8, 9, 10]
+        );
+        assert_eq!(grid.block3(Position { x: 0, y: 0 }).count(), 4);
+    }
+
+    #[test]
+    fn test_neighbours_toward() {
+        let grid = Grid::from_vec(5, 5, (0..25u16).collect()).unwrap();
+        let center = Position { x: 2, y: 2 };
+
+        let toward: Vec<_> = grid
+            .neighbours_toward(center, Position { x: 4, y: 3 })
+            .collect();
+        assert_eq!(toward.len(), 8);
+        assert_eq!(toward[0], (Position { x: 3, y: 3 }, &18));
+        assert_eq!(toward[7].0, Position { x: 1, y: 1 });
+        assert_eq!(
+            grid.neighbours_toward(Position { x: 0, y: 0 }, Position { x: 0, y: 4 })
+                .next(),
+            Some((Position { x: 0, y: 1 }, &5))
+        );
+    }
+
+    #[test]
+    fn test_neighbours_four_connected() {
+        let mut grid = Grid::<u16>::new(10, 10);
+        grid[Position { x: 5, y: 4 }] = 2;
+        grid[Position { x: 4, y: 5 }] = 4;
+        grid[Position { x: 6, y: 5 }] = 6;
+        grid[Position { x: 5, y: 6 }] = 8;
+        grid[Position { x: 4, y: 4 }] = 1;
+
+        let nbrs: Vec<_> = grid
+            .neighbours_with(Position { x: 5, y: 5 }, Connectivity::Four)
+            .collect();
+        assert_eq!(nbrs, vec![&2, &4, &6, &8]);
+
+        let positions: Vec<_> = grid
+            .neighbour_positions_with(Position { x: 0, y: 0 }, Connectivity::Four)
+            .collect();
+        assert_eq!(
+            positions,
+            vec![Position { x: 1, y: 0 }, Position { x: 0, y: 1 }]
+        );
+    }
+
+    #[test]
+    fn test_neighbours_nw_corner() {
+        let mut grid = Grid::<u16>::new(10, 10);
+        grid[Position { x: 0, y: 0 }] = 1;
+        grid[Position { x: 1, y: 0 }] = 2;
+        grid[Position { x: 0, y: 1 }] = 3;
+        grid[Position { x: 1, y: 1 }] = 4;
+
+        let mut nbrs = grid.neighbours(Position { x: 0, y: 0 });
+        assert_eq!(nbrs.next(), Some(&2));
+        assert_eq!(nbrs.next(), Some(&3));
+        assert_eq!(nbrs.next(), Some(&4));
+        assert_eq!(nbrs.next(), None);
+    }
+
+    #[test]
+    fn test_neighbours_ne_corner() {
+        let mut grid = Grid::<u16>::new(10, 10);
+        grid[Position { x: 8, y: 0 }] = 1;
+        grid[Position { x: 9, y: 0 }] = 2;
+        grid[Position { x: 8, y: 1 }] = 3;
+        grid[Position { x: 9, y: 1 }] = 4;
+
+        let mut nbrs = grid.neighbours(Position { x: 9, y: 0 });
+        assert_eq!(nbrs.next(), Some(&1));
+        assert_eq!(nbrs.next(), Some(&3));
+        assert_eq!(nbrs.next(), Some(&4));
+        assert_eq!(nbrs.next(), None);
+    }
+
+    #[test]
+    fn test_neighbours_sw_corner() {
+        let mut grid = Grid::<u16>::new(10, 10);
+        grid[Position { x: 0, y: 8 }] = 1;
+        grid[Position { x: 1, y: 8 }] = 2;
+        grid[Position { x: 0, y: 9 }] = 3;
+        grid[Position { x: 1, y: 9 }] = 4;
+
+        let mut nbrs = grid.neighbours(Position { x: 0, y: 9 });
+        assert_eq!(nbrs.next(), Some(&1));
+        assert_eq!(nbrs.next(), Some(&2));
+        assert_eq!(nbrs.next(), Some(&4));
+        assert_eq!(nbrs.next(), None);
+    }
+
+    #[test]
+    fn test_neighbours_se_corner() {
+        let mut grid = Grid::<u16>::new(10, 10);
+        grid[Position { x: 8, y: 8 }] = 1;
+        grid[Position { x: 9, y: 8 }] = 2;
+        grid[Position { x: 8, y: 9 }] = 3;
+        grid[Position { x: 9, y: 9 }] = 4;
+
+        let mut nbrs = grid.neighbours(Position { x: 9, y: 9 });
+        assert_eq!(nbrs.next(), Some(&1));
+        assert_eq!(nbrs.next(), Some(&2));
+        assert_eq!(nbrs.next(), Some(&3));
+        assert_eq!(nbrs.next(), None);
+    }
+
+    #[test]
+    fn test_positions_in() {
+        let grid = Grid::<u16>::new(10, 10);
+        let positions: Vec<_> = grid.positions_in(Position { x: 2, y: 3 }, 2, 2).collect();
+        assert_eq!(
+            positions,
+            vec![
+                Position { x: 2, y: 3 },
+                Position { x: 3, y: 3 },
+                Position { x: 2, y: 4 },
+                Position { x: 3, y: 4 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_positions_in_clipped() {
+        let grid = Grid::<u16>::new(10, 10);
+        let positions: Vec<_> = grid.positions_in(Position { x: -1, y: 8 }, 3, 3).collect();
+        assert_eq!(
+            positions,
+            vec![
+                Position { x: 0, y: 8 },
+                Position { x: 1, y: 8 },
+                Position { x: 0, y: 9 },
+                Position { x: 1, y: 9 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_spiral_from() {
+        let grid = Grid::<u16>::new(10, 10);
+        let centre = Position { x: 4, y: 4 };
+        let spiral: Vec<_> = grid.spiral_from(centre).collect();
+
+        assert_eq!(spiral.len(), 100);
+        assert_eq!(spiral[0], centre);
+        assert!(spiral[1..9].iter().all(|pos| centre.chebyshev(pos) == 1));
+        assert!(spiral[9..25].iter().all(|pos| centre.chebyshev(pos) == 2));
+        assert!(spiral
+            .windows(2)
+            .all(|pair| centre.chebyshev(&pair[0]) <= centre.chebyshev(&pair[1])));
+    }
+
+    #[test]
+    fn test_spiral_from_corner() {
+        let grid = Grid::<u16>::new(3, 3);
+        let spiral: Vec<_> = grid.spiral_from(Position { x: 0, y: 0 }).collect();
+        assert_eq!(
+            spiral,
+            vec![
+                Position { x: 0, y: 0 },
+                Position { x: 1, y: 0 },
+                Position { x: 0, y: 1 },
+                Position { x: 1, y: 1 },
+                Position { x: 2, y: 0 },
+                Position { x: 2, y: 1 },
+                Position { x: 0, y: 2 },
+                Position { x: 1, y: 2 },
+                Position { x: 2, y: 2 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_edge_tiles() {
+        let grid = Grid::<u16>::new(3, 3);
+        let edges: Vec<_> = grid.edge_tiles().collect();
+        assert_eq!(
+            edges,
+            [
+                (0, 0),
+                (1, 0),
+                (2, 0),
+                (2, 1),
+                (2, 2),
+                (1, 2),
+                (0, 2),
+                (0, 1)
+            ]
+            .map(|(x, y)| Position { x, y })
+        );
+
+        assert_eq!(Grid::<u16>::new(4, 1).edge_tiles().count(), 4);
+        assert_eq!(Grid::<u16>::new(1, 3).edge_tiles().count(), 3);
+        assert_eq!(Grid::<u16>::new(0, 3).edge_tiles().count(), 0);
+        assert_eq!(Grid::<u16>::new(3, 0).edge_tiles().count(), 0);
+        assert_eq!(Grid::<u16>::new(0, 0).edge_tiles().count(), 0);
+    }
+
+    #[test]
+    fn test_affected_tiles() {
+        let grid = Grid::<u16>::new(10, 10);
+        let tiles = grid.affected_tiles(Position { x: 5, y: 5 }, 2);
+        assert_eq!(tiles.len(), 25);
+        assert!(tiles.contains(&Position { x: 3, y: 7 }));
+        assert!(!tiles.contains(&Position { x: 2, y: 5 }));
+    }
+
+    #[test]
+    fn test_affected_tiles_clipped() {
+        let grid = Grid::<u16>::new(10, 10);
+        let tiles = grid.affected_tiles(Position { x: 0, y: 9 }, 1);
+        assert_eq!(
+            tiles,
+            vec![
+                Position { x: 0, y: 8 },
+                Position { x: 1, y: 8 },
+                Position { x: 0, y: 9 },
+                Position { x: 1, y: 9 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_affected_tiles_huge_radius() {
+        let grid = Grid::<u16>::new(4, 3);
+        assert_eq!(
+            grid.affected_tiles(Position { x: 1, y: 1 }, u16::MAX).len(),
+            12
+        );
+        assert_eq!(
+            grid.positions_in(Position { x: 2, y: 1 }, u16::MAX, u16::MAX)
+                .count(),
+            4
+        );
+        assert_eq!(
+            grid.positions_in(Position { x: -5, y: 0 }, 3, u16::MAX)
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_neighbour_counts() {
+        let grid = Grid::<u16>::new(10, 10);
+        let counts = grid.neighbour_counts();
+        for pos in [
+            Position { x: 0, y: 0 },
+            Position { x: 9, y: 9 },
+            Position { x: 5, y: 0 },
+            Position { x: 0, y: 5 },
+            Position { x: 5, y: 5 },
+        ] {
+            assert_eq!(counts[pos] as usize, grid.neighbours(pos).count());
+        }
+        assert_eq!(counts[Position { x: 0, y: 0 }], 3);
+        assert_eq!(counts[Position { x: 5, y: 0 }], 5);
+        assert_eq!(counts[Position { x: 5, y: 5 }], 8);
+    }
+
+    #[test]
+    fn test_smooth_passes() {
+        let grid = Grid::new_random(20, 10);
+        let expected = grid.clone().smooth().smooth().smooth();
+        assert_eq!(grid.smooth_passes(3), expected);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_smooth_matches_sequential() {
+        let grid = Grid::new_random(64, 48);
+        let counts = grid.neighbour_counts();
+        let sequential: Vec<_> = (0..grid.map.len())
+            .map(|idx| grid.smoothed_cell(&counts, idx))
+            .collect();
+
+        assert_eq!(grid.smooth().map, sequential);
+    }
+
+    #[test]
+    fn test_smooth_masked() {
+        let grid = Grid::new_random_with(12, 8, &mut StdRng::seed_from_u64(3));
+        let mut locked = Grid::<bool>::new(12, 8);
+        locked.lock_region(Position { x: 2, y: 2 }, 3, 2);
+
+        let smoothed = grid.clone().smooth_masked(5, &locked).unwrap();
+        for pos in grid.positions_in(Position { x: 0, y: 0 }, 12, 8) {
+            if locked[pos] {
+                assert_eq!(smoothed[pos], grid[pos]);
+            }
+        }
+        assert_ne!(smoothed, grid);
+        assert!(grid.smooth_masked(1, &Grid::new(3, 3)).is_err());
+    }
+
+    #[test]
+    fn test_smooth_constant() {
+        let grid = Grid::from_vec(5, 5, vec![321; 25]).unwrap();
+        assert_eq!(grid.smooth().map, vec![321; 25]);
+    }
+
+    #[test]
+    fn test_heights_above_max_height() {
+        let tall = Grid::from_vec(3, 3, vec![60_000; 9]).unwrap();
+        assert_eq!(tall.smooth().map, vec![60_000; 9]);
+
+        let land = |map: &Grid<Location>| {
+            map.map
+                .iter()
+                .filter(|location| location.terrain == Terrain::Land)
+                .count()
+        };
+        let high = Grid::from_vec(2, 2, vec![2000, 3000, 4000, 5000]).unwrap();
+        assert_eq!(high.try_water_height(50).unwrap(), 4000);
+        assert_eq!(land(&high.clone().make_terrain(50)), 1);
+        assert_eq!(land(&high.clone().make_terrain_land_ratio(50)), 2);
+        assert_eq!(land(&high.make_terrain(100)), 0);
+    }
+
+    #[test]
+    fn test_smooth_spike() {
+        let mut grid = Grid::<u16>::new(5, 5);
+        let centre = Position { x: 2, y: 2 };
+        grid[centre] = 500;
+
+        let smoothed = grid.smooth();
+        assert_eq!(smoothed[centre], 56);
+        for level in smoothed.neighbours(centre) {
+            assert_eq!(*level, 56);
+        }
+        assert_eq!(smoothed[Position { x: 0, y: 0 }], 0);
+    }
+
+    #[test]
+    fn test_gradient_on_ramp() {
+        let mut grid = Grid::<u16>::new(5, 5);
+        for idx in 0..grid.map.len() {
+            grid.map[idx] = 10 * idx_to_pos(idx, 5).x as u16;
+        }
+
+        assert_eq!(grid.gradient(Position { x: 2, y: 2 }), (10, 0));
+        assert_eq!(grid.gradient(Position { x: 0, y: 0 }), (10, 0));
+        assert_eq!(grid.gradient(Position { x: 4, y: 4 }), (10, 0));
+    }
+
+    #[test]
+    fn test_try_water_height() {
+        let flat = Grid::from_vec(4, 4, vec![500; 16]).unwrap();
+        assert!(matches!(
+            flat.try_water_height(50),
+            Err(EmpyreError::UnreachableWaterRatio(50))
+        ));
+        assert_eq!(flat.water_height(50), 500);
+
+        let ramp = Grid::from_vec(4, 1, vec![100, 200, 300, 400]).unwrap();
+        assert_eq!(ramp.try_water_height(50).unwrap(), 300);
+    }
+
+    #[test]
+    fn test_normalized() {
+        let grid = Grid::from_vec(5, 1, vec![200, 700, 450, 300, 200]).unwrap();
+        let normalized = grid.normalized();
+        assert_eq!(normalized.map[0], 0.0);
+        assert_eq!(normalized.map[1], 1.0);
+        assert!((normalized.map[2] - 0.5).abs() < 1e-6);
+        assert_eq!(
+            Grid::from_vec(2, 1, vec![5, 5]).unwrap().normalized().map,
+            vec![0.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn test_center_bias() {
+        let grid = Grid::new_random_with(40, 30, &mut StdRng::seed_from_u64(9)).smooth_passes(2);
+        assert_eq!(grid.clone().center_bias(0.0), grid);
+
+        let map = grid.center_bias(1.0).make_terrain(50);
+        let edges: Vec<_> = map.edge_tiles().collect();
+        let water = edges
+            .iter()
+            .filter(|pos| map[**pos].terrain == Terrain::Water)
+            .count();
+        assert!(water * 10 > edges.len() * 9);
+    }
+
+    #[test]
+    fn test_extreme_water_ratios() {
+        let grid = Grid::new_random_with(30, 20, &mut StdRng::seed_from_u64(8)).smooth();
+        let land = |map: &Grid<Location>| {
+            map.map
+                .iter()
+                .filter(|location| location.terrain == Terrain::Land)
+                .count()
+        };
+
+        let mut all_water = grid.clone().make_terrain(100);
+        all_water.place_cities_with(&mut StdRng::seed_from_u64(8));
+        assert_eq!(land(&all_water), 0);
+        assert_eq!(all_water.pieces().count(), 0);
+
+        let mut mostly_land = grid.make_terrain(0);
+        mostly_land.place_cities_with(&mut StdRng::seed_from_u64(8));
+        assert!(land(&mostly_land) > 590);
+        assert_eq!(mostly_land.pieces().count(), mostly_land.city_count());
+
+        let mut all_land = ascii_map(&["++++++++++"; 10]);
+        all_land.place_cities_with(&mut StdRng::seed_from_u64(8));
+        assert!((1..=all_land.city_count()).contains(&all_land.pieces().count()));
+
+        let mut tiny = ascii_map(&["+"]);
+        tiny.place_cities_with(&mut StdRng::seed_from_u64(8));
+        assert_eq!(tiny.pieces().count(), 0);
+        assert!(Grid::<u16>::new(0, 0).try_water_height(50).is_err());
+    }
+
+    #[test]
+    fn test_make_terrain_land_ratio() {
+        let grid = Grid::new_random_with(50, 40, &mut StdRng::seed_from_u64(10)).smooth_passes(3);
+        let land = |map: &Grid<Location>| {
+            map.map
+                .iter()
+                .filter(|location| location.terrain == Terrain::Land)
+                .count()
+        };
+
+        let map = grid.clone().make_terrain_land_ratio(30);
+        let percent = land(&map) * 100 / map.map.len();
+        assert!((30..=32).contains(&percent), "{percent}% land");
+
+        assert_eq!(land(&grid.clone().make_terrain_land_ratio(0)), 0);
+        assert!(land(&grid.make_terrain_land_ratio(100)) >= 1999);
+    }
+
+    #[test]
+    fn test_reclassify() {
+        let mut map = Grid::new_random_with(20, 20, &mut StdRng::seed_from_u64(12))
+            .smooth_passes(2)
+            .make_terrain(30);
+        let land = |map: &Grid<Location>| {
+            map.map
+                .iter()
+                .filter(|location| location.terrain == Terrain::Land)
+                .count()
+        };
+        let before = land(&map);
+
+        map.reclassify(70).unwrap();
+        assert!(land(&map) < before);
+    }
+
+    #[test]
+    fn test_reclassify_keeps_mountains_and_cities() {
+        let mut map = Grid::new_random_with(20, 20, &mut StdRng::seed_from_u64(12))
+            .smooth_passes(2)
+            .make_terrain(30);
+        let (peak, city) = (Position { x: 0, y: 0 }, Position { x: 19, y: 19 });
+        map[peak].terrain = Terrain::Mountain;
+        map[city].terrain = Terrain::Land;
+        map[city].height = 0;
+        map.put_piece(Piece::City, city);
+
+        map.reclassify(90).unwrap();
+        assert_eq!(map[peak].terrain, Terrain::Mountain);
+        assert_eq!(map[city].terrain, Terrain::Land);
+        assert_eq!(map[city].piece(), Some(&Piece::City));
+    }
+
+    #[test]
+    fn test_reclassify_without_heights() {
+        let mut map = ascii_map(&["+O.", "++."]);
+        let before = map.clone();
+        assert!(matches!(
+            map.reclassify(50),
+            Err(EmpyreError::UnreachableWaterRatio(50))
+        ));
+        assert_eq!(map, before);
+    }
+
+    #[test]
+    fn test_seeded_pipeline_is_reproducible() {
+        let generate = || {
+            let mut rng = StdRng::seed_from_u64(42);
+            let mut map = Grid::new_random_with(30, 20, &mut rng)
+                .smooth_passes(3)
+                .make_terrain(60);
+            map.place_cities_with(&mut rng);
+            map.to_string()
+        };
+        assert_eq!(generate(), generate());
+    }
+
+    fn ascii_map(rows: &[&str]) -> Grid<Location> {
+        Grid::from_ascii(&rows.join("\n")).unwrap()
+    }
+
+    #[test]
+    fn test_from_ascii_ragged() {
+        assert!(matches!(
+            Grid::from_ascii("+++\n++\n+++\n"),
+            Err(EmpyreError::RaggedMap {
+                row: 1,
+                expected: 3,
+                found: 2
+            })
+        ));
+        assert!(matches!(
+            Grid::from_ascii("+++\n+++\n++++\n"),
+            Err(EmpyreError::RaggedMap {
+                row: 2,
+                expected: 3,
+                found: 4
+            })
+        ));
+    }
+
+    #[test]
+    fn test_neighbour_positions() {
+        let grid = Grid::<u16>::new(10, 10);
+        let positions: Vec<_> = grid.neighbour_positions(Position { x: 0, y: 1 }).collect();
+        assert_eq!(
+            positions,
+            vec![
+                Position { x: 0, y: 0 },
+                Position { x: 1, y: 0 },
+                Position { x: 1, y: 1 },
+                Position { x: 0, y: 2 },
+                Position { x: 1, y: 2 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_place_piece_relocating() {
+        let mut map = ascii_map(&["....", "...+", "O+.."]);
+
+        let landed = map.place_piece_relocating(Piece::City, Position { x: 1, y: 1 });
+        assert_eq!(landed.unwrap(), Position { x: 1, y: 2 });
+        let landed = map.place_piece_relocating(Piece::City, Position { x: 3, y: 1 });
+        assert_eq!(landed.unwrap(), Position { x: 3, y: 1 });
+        assert!(matches!(
+            map.place_piece_relocating(Piece::City, Position { x: 2, y: 0 }),
+            Err(EmpyreError::NoFreeTile(_))
+        ));
+        assert!(map
+            .place_piece_relocating(Piece::City, Position { x: 4, y: 0 })
+            .is_err());
+    }
+
+    #[test]
+    fn test_landing_tiles() {
+        let map = ascii_map(&["+O+.", "...+", "....", "++.."]);
+        assert_eq!(
+            map.landing_tiles(Position { x: 2, y: 1 }),
+            vec![Position { x: 2, y: 0 }, Position { x: 3, y: 1 }]
+        );
+        assert_eq!(map.landing_tiles(Position { x: 2, y: 2 }).len(), 2);
+        assert!(ascii_map(&["...", "...", "..."])
+            .landing_tiles(Position { x: 1, y: 1 })
+            .is_empty());
+    }
+
+    #[test]
+    fn test_bombard_targets() {
+        let map = ascii_map(&["O+O+O", "+O+++", "O++++", "++++O"]);
+        let hit: Vec<Position> = map
+            .bombard_targets(Position { x: 1, y: 1 }, 1)
+            .into_iter()
+            .map(|(pos, _)| pos)
+            .collect();
+        assert_eq!(
+            hit,
+            vec![
+                Position { x: 0, y: 0 },
+                Position { x: 2, y: 0 },
+                Position { x: 1, y: 1 },
+                Position { x: 0, y: 2 },
+            ]
+        );
+        assert!(map.bombard_targets(Position { x: 3, y: 2 }, 0).is_empty());
+    }
+
+    #[test]
+    fn test_set_terrain() {
+        let mut map = ascii_map(&["+O", ".."]);
+        map.set_terrain(Position { x: 0, y: 1 }, Terrain::Land)
+            .unwrap();
+        assert_eq!(map.to_string(), "+O\n+.\n");
+
+        assert!(matches!(
+            map.set_terrain(Position { x: 1, y: 0 }, Terrain::Water),
+            Err(EmpyreError::MisplacedPiece { .. })
+        ));
+        assert!(matches!(
+            map.set_terrain(Position { x: 2, y: 0 }, Terrain::Land),
+            Err(EmpyreError::OutOfBounds(Position { x: 2, y: 0 }))
+        ));
+        assert_eq!(map.to_string(), "+O\n+.\n");
+    }
+
+    #[test]
+    fn test_wall_border() {
+        let mut map = ascii_map(&["+++++", "O++++", "+++++", "+++++", "+++++"]);
+        map.wall_border(2);
+        assert_eq!(map.to_string(), "^^^^^\n^^^^^\n^^+^^\n^^^^^\n^^^^^\n");
+
+        let mut map = ascii_map(&["++++", "++++", "++++"]);
+        map.wall_border(1);
+        let passable = |location: &Location| location.terrain.is_passable();
+        assert!(map
+            .find_path(Position { x: 1, y: 1 }, Position { x: 2, y: 1 }, passable)
+            .is_some());
+        assert!(map
+            .find_path(Position { x: 1, y: 1 }, Position { x: 0, y: 1 }, passable)
+            .is_none());
+        assert!(!Piece::City.can_occupy(&Terrain::Mountain));
+    }
+
+    #[test]
+    fn test_buildability() {
+        let mut map = ascii_map(&[
+            "....................",
+            ".++++++++++++++++++.",
+            ".++++++++++++++++++.",
+            ".++++++++++++++++++.",
+            "....................",
+            ".................+..",
+        ]);
+        map.put_piece(Piece::City, Position { x: 2, y: 2 });
+        let scores = map.buildability();
+
+        let crammed = scores[Position { x: 3, y: 2 }];
+        let far = scores[Position { x: 16, y: 2 }];
+        assert!(far > crammed);
+        assert!(far > scores[Position { x: 17, y: 5 }]);
+        assert_eq!(scores[Position { x: 2, y: 2 }], 0.0);
+        assert_eq!(scores[Position { x: 0, y: 0 }], 0.0);
+    }
+
+    #[test]
+    fn test_free_adjacent_open() {
+        let map = ascii_map(&["+++", "+O+", "+++"]);
+        assert_eq!(
+            map.free_adjacent(Position { x: 1, y: 1 }, &Piece::City),
+            Some(Position { x: 0, y: 0 })
+        );
+    }
+
+    #[test]
+    fn test_free_adjacent_surrounded() {
+        let map = ascii_map(&["OOO", "OOO", "OOO"]);
+        assert_eq!(
+            map.free_adjacent(Position { x: 1, y: 1 }, &Piece::City),
+            None
+        );
+    }
+
+    #[test]
+    fn test_free_adjacent_coastal() {
+        let map = ascii_map(&["...", ".O.", "..+"]);
+        assert_eq!(
+            map.free_adjacent(Position { x: 1, y: 1 }, &Piece::City),
+            Some(Position { x: 2, y: 2 })
+        );
+    }
+
+    #[test]
+    fn test_symmetric_terrain() {
+        for axis in [
+            SymmetryAxis::Horizontal,
+            SymmetryAxis::Vertical,
+            SymmetryAxis::Point,
+        ] {
+            let mut map = Grid::new_symmetric(21, 14, 7, axis)
+                .smooth_passes(3)
+                .make_terrain(50);
+            map.place_cities_symmetric(axis, &mut StdRng::seed_from_u64(7));
+
+            for pos in map.positions_in(Position { x: 0, y: 0 }, map.width, map.height) {
+                let mirror = &map[map.mirror(pos, axis)];
+                assert!(map[pos].terrain == mirror.terrain);
+                assert_eq!(map[pos].piece.is_some(), mirror.piece.is_some());
+            }
+            assert!(map.validate().is_ok());
+        }
+    }
+
+    #[test]
+    fn test_symmetric_cities_fit_both_halves() {
+        let mut lopsided = ascii_map(&["+.....", "......"]);
+        lopsided.place_cities_symmetric(SymmetryAxis::Vertical, &mut StdRng::seed_from_u64(2));
+        assert_eq!(lopsided.pieces().count(), 0);
+        assert!(lopsided.validate().is_ok());
+
+        let mut open = ascii_map(&["++++++++++++++++++++"; 12]);
+        open.place_cities_symmetric(SymmetryAxis::Point, &mut StdRng::seed_from_u64(2));
+        let cities: Vec<Position> = open.pieces().map(|(pos, _)| pos).collect();
+        assert!(cities.len() >= 2 && cities.len().is_multiple_of(2));
+        let min_dist = isqrt(240 / open.city_count());
+        for (i, a) in cities.iter().enumerate() {
+            assert!(cities[i + 1..].iter().all(|b| a.distance(b) >= min_dist));
+        }
+    }
+
+    #[test]
+    fn test_symmetry_score() {
+        let symmetric = Grid::new_symmetric(30, 20, 11, SymmetryAxis::Vertical)
+            .smooth_passes(3)
+            .make_terrain(50);
+        assert_eq!(symmetric.symmetry_score(SymmetryAxis::Vertical), 1.0);
+
+        let random = Grid::new_random_with(30, 20, &mut StdRng::seed_from_u64(11)).make_terrain(50);
+        assert!(random.symmetry_score(SymmetryAxis::Vertical) < 0.8);
+        assert_eq!(
+            ascii_map(&["+.", "+."]).symmetry_score(SymmetryAxis::Vertical),
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_land_mask() {
+        let mut map = ascii_map(&["+.O", ". +"]);
+        let mask = map.land_mask();
+        assert_eq!(mask.map, vec![true, false, true, false, false, true]);
+
+        let mut flipped = mask.clone();
+        flipped.map.iter_mut().for_each(|land| *land = !*land);
+        map.apply_land_mask(&flipped).unwrap();
+        assert_eq!(map.to_string(), ".+.\n+ .\n");
+        assert!(map.apply_land_mask(&Grid::new(2, 2)).is_err());
+
+        let mut mixed = ascii_map(&["^+."]);
+        let mask = mixed.land_mask();
+        mixed.apply_land_mask(&mask).unwrap();
+        assert_eq!(mixed.to_string(), "^+.\n");
+        mixed
+            .apply_land_mask(&Grid::from_vec(3, 1, vec![true; 3]).unwrap())
+            .unwrap();
+        assert_eq!(mixed.to_string(), "^++\n");
+    }
+
+    #[test]
+    fn test_write_to() {
+        let map = ascii_map(&["+.O", "..+"]);
+        let mut out = Vec::new();
+        map.write_to(&mut out).unwrap();
+
+        assert_eq!(out, b"+.O\n..+\n");
+        assert_eq!(String::from_utf8(out).unwrap(), map.to_string());
+    }
+
+    #[test]
+    fn test_rows() {
+        let mut map = Grid::new_random(30, 12).smooth_passes(3).make_terrain(50);
+        map.place_cities();
+
+        let streamed: String = map.rows().map(|row| row + "\n").collect();
+        assert_eq!(streamed, map.to_string());
+        assert_eq!(
+            ascii_map(&["+.O", "..+"]).rows().collect::<Vec<_>>(),
+            ["+.O", "..+"]
+        );
+    }
+
+    #[test]
+    fn test_jitter_coastline() {
+        let rows = ["........", "........", "...+++++", "...+++++", "...+++++"];
+        let before = ascii_map(&rows);
+        let mut map = ascii_map(&rows);
+        map.jitter_coastline(100, &mut StdRng::seed_from_u64(1));
+        assert_eq!(
+            map.to_string(),
+            "........\n..++++++\n..+.....\n..+.++++\n..+.++++\n"
+        );
+
+        let mut map = ascii_map(&rows);
+        map.jitter_coastline(50, &mut StdRng::seed_from_u64(1));
+        let distances = before.distance_to_water();
+        for pos in map.positions_in(Position { x: 0, y: 0 }, 8, 5) {
+            let deep = before[pos].terrain == Terrain::Water
+                && before.neighbours(pos).all(|n| n.terrain == Terrain::Water);
+            if deep || distances[pos] > 1 {
+                assert_eq!(map[pos].terrain, before[pos].terrain);
+            }
+        }
+        assert_ne!(map, before);
+    }
+
+    #[test]
+    fn test_erode_isolated_land() {
+        let mut map = ascii_map(&[".....", ".....", "..+..", ".....", "....."]);
+        map.erode(2);
+        assert_eq!(map.to_string(), ".....\n.....\n.....\n.....\n.....\n");
+    }
+
+    #[test]
+    fn test_erode_keeps_uniform_maps() {
+        for row in ["++++", "...."] {
+            let mut map = ascii_map(&[row, row, row]);
+            let before = map.clone();
+            map.erode(8);
+            assert_eq!(map, before);
+            map.smooth_terrain(3, 4);
+            assert_eq!(map, before);
+        }
+    }
+
+    #[test]
+    fn test_erode_fills_lake() {
+        let mut map = ascii_map(&["+++++", "+++++", "++.++", "+++++", "+++++"]);
+        map.erode(2);
+        assert_eq!(map.to_string(), "+++++\n+++++\n+++++\n+++++\n+++++\n");
+    }
+
+    #[test]
+    fn test_smooth_terrain() {
+        let mut map = ascii_map(&[
+            "..........",
+            ".++++++...",
+            ".+++.++...",
+            ".O+++++.+.",
+            ".++++++...",
+            "........+.",
+            "..........",
+        ]);
+        map.smooth_terrain(3, 2);
+
+        assert_eq!(map[Position { x: 4, y: 2 }].terrain, Terrain::Land);
+        assert_eq!(map[Position { x: 8, y: 3 }].terrain, Terrain::Water);
+        assert_eq!(map[Position { x: 8, y: 5 }].terrain, Terrain::Water);
+        assert_eq!(map[Position { x: 1, y: 3 }].piece, Some(Piece::City));
+        assert_eq!(map[Position { x: 1, y: 3 }].terrain, Terrain::Land);
+    }
+
+    #[test]
+    fn test_distance_to_water() {
+        let map = ascii_map(&[".....", ".+++.", ".+++.", ".+++.", "....."]);
+        let distances = map.distance_to_water();
+        assert_eq!(distances[Position { x: 0, y: 0 }], 0);
+        assert_eq!(distances[Position { x: 1, y: 2 }], 1);
+        assert_eq!(distances[Position { x: 2, y: 2 }], 2);
+    }
+
+    #[test]
+    fn test_continent_center() {
+        let map = ascii_map(&[
+            ".........",
+            "...+++...",
+            "..+++++..",
+            ".+++++++.",
+            ".+++++++.",
+            ".+++++++.",
+            "..+++++..",
+            "...+++...",
+            ".........",
+        ]);
+        let island: Vec<_> = map
+            .map
+            .iter()
+            .filter(|l| l.terrain == Terrain::Land)
+            .map(|l| l.pos)
+            .collect();
+
+        assert_eq!(map.continent_center(&island), Some(Position { x: 4, y: 4 }));
+        assert_eq!(map.continent_center(&[]), None);
+    }
+
+    #[test]
+    fn test_trim_water_border() {
+        let mut map = ascii_map(&["......", "......", "..+...", "...+..", "......", "......"]);
+        map.put_piece(Piece::City, Position { x: 3, y: 3 });
+        let trimmed = map.trim_water_border();
+
+        assert_eq!(trimmed.to_string(), "+.\n.O\n");
+        assert_eq!(
+            trimmed[Position { x: 1, y: 1 }].position(),
+            Position { x: 1, y: 1 }
+        );
+        assert!(trimmed.validate().is_ok());
+        assert_eq!(ascii_map(&["..", ".."]).trim_water_border().width(), 0);
+    }
+
+    #[test]
+    fn test_crop_map() {
+        let mut map = ascii_map(&["+....", ".++..", ".+O..", "....+"]);
+        map.put_piece(Piece::City, Position { x: 1, y: 1 });
+        let cropped = map.crop(Position { x: 1, y: 1 }, 3, 2).unwrap();
+
+        assert_eq!(
+            cropped.to_string(),
+            "O+.
++O.
+"
+        );
+        assert_eq!(cropped.landmasses().len(), 1);
+        assert!(cropped.validate().is_ok());
+        let cities: Vec<Position> = cropped.pieces().map(|(pos, _)| pos).collect();
+        assert_eq!(
+            cities,
+            vec![Position { x: 0, y: 0 }, Position { x: 1, y: 1 }]
+        );
+        assert!(map.crop(Position { x: 3, y: 3 }, 3, 2).is_err());
+
+        let corner = map.crop(Position { x: 3, y: 2 }, 2, 2).unwrap();
+        assert!(corner.validate().is_ok());
+        assert_eq!(corner.landmasses(), vec![vec![Position { x: 1, y: 1 }]]);
+    }
+
+    #[test]
+    fn test_chokepoints() {
+        let hourglass = ascii_map(&["+++", "+++", ".+.", "+++", "+++"]);
+        assert_eq!(hourglass.chokepoints(), vec![Position { x: 1, y: 2 }]);
+
+        let line = ascii_map(&["++++", "....", "+..."]);
+        assert_eq!(
+            line.chokepoints(),
+            vec![Position { x: 1, y: 0 }, Position { x: 2, y: 0 }]
+        );
+        assert!(ascii_map(&["+++", "+++"]).chokepoints().is_empty());
+
+        let map = Grid::new_random_with(16, 10, &mut StdRng::seed_from_u64(5))
+            .smooth()
+            .make_terrain(50);
+        let count = map.landmasses().len();
+        let brute_force: Vec<_> = map
+            .positions_in(Position { x: 0, y: 0 }, map.width, map.height)
+            .filter(|pos| map[*pos].terrain == Terrain::Land)
+            .filter(|pos| {
+                let mut without = map.clone();
+                without[*pos].terrain = Terrain::Water;
+                without.landmasses().len() > count
+            })
+            .collect();
+        assert_eq!(map.chokepoints(), brute_force);
+    }
+
+    #[test]
+    fn test_find_path() {
+        let map = ascii_map(&["+...", "+++.", "....", ".+++"]);
+        let land = |location: &Location| location.terrain == Terrain::Land;
+        let path = map.find_path(Position { x: 0, y: 0 }, Position { x: 2, y: 1 }, land);
+        assert_eq!(
+            path.unwrap(),
+            vec![
+                Position { x: 0, y: 0 },
+                Position { x: 1, y: 1 },
+                Position { x: 2, y: 1 }
+            ]
+        );
+        assert!(map
+            .find_path(Position { x: 0, y: 0 }, Position { x: 1, y: 3 }, land)
+            .is_none());
+        assert_eq!(
+            map.find_path(Position { x: 0, y: 0 }, Position { x: 0, y: 0 }, land),
+            Some(vec![Position { x: 0, y: 0 }])
+        );
     }
-}
 
-impl<T> Display for Grid<T>
-where
-    T: Display,
-{
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for h in 0..self.height {
-            for w in 0..self.width {
-                write!(
-                    f,
-                    "{}",
-                    self[Position {
-                        x: w as i16,
-                        y: h as i16
-                    }]
-                )?
-            }
-            write!(f, "\n")?
-        }
-        Ok(())
+    #[test]
+    fn test_find_path_four_connected() {
+        let map = ascii_map(&["+++", "+++", "+++"]);
+        let land = |location: &Location| location.terrain == Terrain::Land;
+        let (from, to) = (Position { x: 0, y: 0 }, Position { x: 2, y: 2 });
+        let four_connected = Movement {
+            connectivity: Connectivity::Four,
+            ..Movement::default()
+        };
+        let eight = map.find_path_with(from, to, Movement::default(), land);
+        let four = map.find_path_with(from, to, four_connected, land).unwrap();
+        assert_eq!(eight.unwrap().len(), 3);
+        assert_eq!(four.len(), 5);
+        assert!(four
+            .windows(2)
+            .all(|step| step[0].x == step[1].x || step[0].y == step[1].y));
+
+        let diagonal_only = ascii_map(&["+.", ".+"]);
+        assert!(diagonal_only
+            .find_path_with(from, Position { x: 1, y: 1 }, four_connected, land)
+            .is_none());
     }
-}
 
-pub struct NeighbourIter<'g, T> {
-    grid: &'g Grid<T>,
-    pos: Position,
-    dirs: Vec<Position>,
-    idx: usize,
-}
+    #[test]
+    fn test_find_path_without_corner_cutting() {
+        let land = |location: &Location| location.terrain == Terrain::Land;
+        let strict = Movement {
+            cut_corners: false,
+            ..Movement::default()
+        };
+        let (from, to) = (Position { x: 0, y: 0 }, Position { x: 1, y: 1 });
 
-impl<'g, T> Iterator for NeighbourIter<'g, T> {
-    type Item = &'g T;
+        let squeeze = ascii_map(&["+.", ".+"]);
+        assert!(squeeze.find_path_with(from, to, strict, land).is_none());
+        assert_eq!(squeeze.find_path(from, to, land).unwrap().len(), 2);
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.idx >= self.dirs.len() {
-            return None;
-        }
+        let open = ascii_map(&["++", ".+"]);
+        assert_eq!(
+            open.find_path_with(from, to, strict, land),
+            Some(vec![from, to])
+        );
+    }
 
-        loop {
-            let n_pos = self.pos + self.dirs[self.idx];
-            self.idx += 1;
+    #[test]
+    fn test_find_path_is_deterministic() {
+        let map = Grid::new_random_with(40, 30, &mut StdRng::seed_from_u64(6))
+            .smooth_passes(2)
+            .make_terrain(30);
+        let (from, to) = (Position { x: 0, y: 0 }, Position { x: 39, y: 29 });
+        let anything = |_: &Location| true;
 
-            if self.grid.covers(n_pos) {
-                return Some(&self.grid[n_pos]);
-            }
+        let path = map.find_path(from, to, anything).unwrap();
+        assert_eq!(path.len(), 40);
+        assert_eq!(map.find_path(from, to, anything).unwrap(), path);
 
-            if self.idx >= self.dirs.len() {
-                return None;
-            }
-        }
+        let open = ascii_map(&["+++", "+++", "+++"]);
+        assert_eq!(
+            open.find_path(Position { x: 0, y: 0 }, Position { x: 2, y: 1 }, anything),
+            Some(vec![
+                Position { x: 0, y: 0 },
+                Position { x: 1, y: 0 },
+                Position { x: 2, y: 1 }
+            ])
+        );
+        let land = |location: &Location| location.terrain == Terrain::Land;
+        let first = map.find_path(from, to, land);
+        assert_eq!(map.find_path(from, to, land), first);
     }
-}
 
-fn pos_to_idx(pos: Position, width: u16) -> usize {
-    (pos.y * width as i16 + pos.x) as usize
-}
+    #[test]
+    fn test_find_path_large_maze() {
+        // Walls every fourth column with the gap alternating between the top
+        // and bottom rows, so the only route snakes across the whole map.
+        let (width, height) = (400, 200);
+        let rows: Vec<String> = (0..height)
+            .map(|y| {
+                (0..width)
+                    .map(|x| {
+                        let wall = x % 4 == 2;
+                        let gap = if x % 8 == 2 { height - 1 } else { 0 };
+                        if wall && y != gap {
+                            '.'
+                        } else {
+                            '+'
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        let map = Grid::from_ascii(&(rows.join("\n") + "\n")).unwrap();
 
-fn idx_to_pos(idx: usize, width: u16) -> Position {
-    let y = idx as u16 / width;
-    Position {
-        x: (idx - (y * width) as usize) as i16,
-        y: y as i16,
+        let start = std::time::Instant::now();
+        let path = map
+            .find_path(
+                Position { x: 0, y: 0 },
+                Position {
+                    x: width as i16 - 1,
+                    y: 0,
+                },
+                |location| location.terrain == Terrain::Land,
+            )
+            .unwrap();
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+
+        assert!(path.len() > 90 * height);
+        assert!(path.windows(2).all(|step| step[0].chebyshev(&step[1]) == 1));
     }
-}
 
-// See https://en.wikipedia.org/wiki/Integer_square_root
-fn isqrt(val: usize) -> usize {
-    let mut left = 0;
-    let mut mid = 0;
-    let mut right = val + 1;
+    #[test]
+    fn test_shortest_sea_route() {
+        let map = ascii_map(&["+.....+", "+++++++", "+..+..+", "+++++++"]);
+        let route = map
+            .shortest_sea_route(Position { x: 0, y: 0 }, Position { x: 6, y: 0 })
+            .unwrap();
+        assert_eq!(route.len(), 7);
+        assert!(route[1..6]
+            .iter()
+            .all(|pos| map[*pos].terrain == Terrain::Water));
 
-    while left != right - 1 {
-        mid = (left + right) / 2;
+        assert!(map
+            .shortest_sea_route(Position { x: 0, y: 2 }, Position { x: 6, y: 2 })
+            .is_none());
+    }
 
-        if mid * mid <= val {
-            left = mid;
-        } else {
-            right = mid;
-        }
+    #[test]
+    fn test_shortest_sea_route_avoids_land() {
+        let (west, east) = (Position { x: 1, y: 1 }, Position { x: 2, y: 1 });
+        let inland = ascii_map(&["++++", "+OO+", "++++", "...."]);
+        assert!(inland.shortest_sea_route(west, east).is_none());
+
+        let coastal = ascii_map(&["....", ".OO.", "++++"]);
+        let route = coastal.shortest_sea_route(west, east).unwrap();
+        assert_eq!(route.len(), 3);
+        assert_eq!((route[0], route[2]), (west, east));
+        assert_eq!(coastal[route[1]].terrain, Terrain::Water);
     }
-    left
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_explore_turns() {
+        let strip = ascii_map(&["..........", ".++++++++.", ".........."]);
+        let start = Position { x: 1, y: 1 };
+        assert_eq!(strip.explore_turns(start, 1, 1), 6);
+        assert_eq!(strip.explore_turns(start, 1, 2), 3);
+        assert_eq!(strip.explore_turns(start, 9, 1), 0);
+        assert_eq!(strip.explore_turns(Position { x: 0, y: 0 }, 1, 1), 0);
+
+        let map = Grid::new_random_with(30, 20, &mut StdRng::seed_from_u64(4))
+            .smooth_passes(3)
+            .make_terrain(40);
+        let start = map
+            .landmasses()
+            .into_iter()
+            .max_by_key(|l| l.len())
+            .unwrap()[0];
+        let turns = map.explore_turns(start, 2, 3);
+        assert!(turns > 0 && turns < 600);
+    }
 
     #[test]
-    fn test_new_grid() {
+    fn test_seas() {
+        let map = ascii_map(&["..+..", "+++++", ".+.+."]);
+        let mut sizes: Vec<_> = map.seas().iter().map(|sea| sea.len()).collect();
+        sizes.sort();
+        assert_eq!(sizes, vec![1, 1, 1, 2, 2]);
+
+        let ocean = Grid::from_ascii(&format!("{}\n", ".".repeat(400)).repeat(250)).unwrap();
+        let seas = ocean.seas();
+        assert_eq!(seas.len(), 1);
+        assert_eq!(seas[0].len(), 100_000);
+    }
+
+    #[test]
+    fn test_continent_centroids() {
+        let map = ascii_map(&[
+            "..........",
+            ".+++++....",
+            ".+++++....",
+            ".+++++...+",
+            "........++",
+        ]);
         assert_eq!(
-            Grid::new(10, 20),
-            Grid::<u16> {
-                width: 10,
-                height: 20,
-                map: vec![0; 200]
-            }
+            map.continent_centroids(),
+            vec![Position { x: 3, y: 2 }, Position { x: 9, y: 4 }]
         );
+        assert!(ascii_map(&["..", ".."]).continent_centroids().is_empty());
     }
 
     #[test]
-    fn test_grid_index() {
-        let mut grid = Grid::<i32>::new(DEFAULT_MAP_WIDTH, DEFAULT_MAP_HEIGHT);
-        let pos = Position { x: 5, y: 5 };
-        grid[pos] = 1;
-        assert!(grid[pos] == 1);
+    fn test_continent_diameter() {
+        let map = ascii_map(&[
+            "................",
+            ".++++++++++++++.",
+            "..+++++++++++++.",
+            "................",
+        ]);
+        let continent = &map.landmasses()[0];
+        assert_eq!(map.continent_diameter(continent), 13);
+
+        let hook = ascii_map(&["+++++", "....+", "+++++"]);
+        assert_eq!(hook.continent_diameter(&hook.landmasses()[0]), 8);
+        assert_eq!(hook.continent_diameter(&[]), 0);
     }
 
     #[test]
-    fn test_add_position() {
-        let p1 = Position { x: 1, y: 2 };
-        let p2 = Position { x: -1, y: 3 };
-        assert_eq!(p1 + p2, Position { x: 0, y: 5 })
+    fn test_max_corridor_width() {
+        let broad = ascii_map(&[
+            "...........",
+            ".+++++++++.",
+            ".+++++++++.",
+            ".+++++++++.",
+            ".+++++++++.",
+            ".+++++++++.",
+            "...........",
+        ]);
+        let isthmus = ascii_map(&["...........", ".+++++++++.", "..........."]);
+        assert_eq!(broad.max_corridor_width(&broad.landmasses()[0]), 6);
+        assert_eq!(isthmus.max_corridor_width(&isthmus.landmasses()[0]), 2);
+        assert_eq!(isthmus.max_corridor_width(&[]), 0);
+
+        let pair = ascii_map(&["...........", ".+++++++++.", ".+++++++++.", "..........."]);
+        let triple = ascii_map(&["......", ".++++.", ".++++.", ".++++.", "......"]);
+        assert_eq!(pair.max_corridor_width(&pair.landmasses()[0]), 2);
+        assert_eq!(triple.max_corridor_width(&triple.landmasses()[0]), 4);
+
+        let off_map = [Position { x: -1, y: 0 }, Position { x: 40, y: 1 }];
+        assert_eq!(isthmus.max_corridor_width(&off_map), 0);
+        assert_eq!(isthmus.continent_diameter(&off_map), 0);
     }
 
     #[test]
-    fn test_neighbours() {
-        let mut grid = Grid::<u16>::new(10, 10);
-        grid[Position { x: 4, y: 4 }] = 1;
-        grid[Position { x: 5, y: 4 }] = 2;
-        grid[Position { x: 6, y: 4 }] = 3;
-        grid[Position { x: 4, y: 5 }] = 4;
-        grid[Position { x: 5, y: 5 }] = 5;
-        grid[Position { x: 6, y: 5 }] = 6;
-        grid[Position { x: 4, y: 6 }] = 7;
-        grid[Position { x: 5, y: 6 }] = 8;
-        grid[Position { x: 6, y: 6 }] = 9;
+    fn test_continent_gap() {
+        let map = ascii_map(&["+.....+", "+.....+", "++...++", "+.....+", "+.....+"]);
+        let landmasses = map.landmasses();
+        assert_eq!(landmasses.len(), 2);
+        assert_eq!(
+            map.continent_gap(&landmasses[0], &landmasses[1]),
+            Some((Position { x: 1, y: 2 }, Position { x: 5, y: 2 }, 4))
+        );
 
-        let mut nbrs = grid.neighbours(Position { x: 5, y: 5 });
-        assert_eq!(nbrs.next(), Some(&1));
-        assert_eq!(nbrs.next(), Some(&2));
-        assert_eq!(nbrs.next(), Some(&3));
-        assert_eq!(nbrs.next(), Some(&4));
-        assert_eq!(nbrs.next(), Some(&6));
-        assert_eq!(nbrs.next(), Some(&7));
-        assert_eq!(nbrs.next(), Some(&8));
-        assert_eq!(nbrs.next(), Some(&9));
-        assert_eq!(nbrs.next(), None);
+        let inland = [Position { x: 1, y: 1 }];
+        let solid = ascii_map(&["+++", "+++", "+++"]);
+        assert_eq!(
+            solid.continent_gap(&inland, &[Position { x: 0, y: 0 }]),
+            None
+        );
     }
 
     #[test]
-    fn test_neighbours_nw_corner() {
-        let mut grid = Grid::<u16>::new(10, 10);
-        grid[Position { x: 0, y: 0 }] = 1;
-        grid[Position { x: 1, y: 0 }] = 2;
-        grid[Position { x: 0, y: 1 }] = 3;
-        grid[Position { x: 1, y: 1 }] = 4;
+    fn test_translate_positions_after_crop() {
+        let mut map = ascii_map(&["......", "..+O..", "..++..", "......"]);
+        let city = Position { x: 3, y: 1 };
 
-        let mut nbrs = grid.neighbours(Position { x: 0, y: 0 });
-        assert_eq!(nbrs.next(), Some(&2));
-        assert_eq!(nbrs.next(), Some(&3));
-        assert_eq!(nbrs.next(), Some(&4));
-        assert_eq!(nbrs.next(), None);
+        let (cropped, offset) = map.crop_with_offset(Position { x: 1, y: 1 }, 4, 2).unwrap();
+        let rebased = translate_positions(&[city], offset);
+        assert_eq!(rebased, vec![Position { x: 2, y: 0 }]);
+        assert_eq!(cropped[rebased[0]].piece, Some(Piece::City));
+
+        map.remove_piece(city);
+        map.put_piece(Piece::City, Position { x: 2, y: 2 });
+        let (trimmed, offset) = map.trim_water_border_with_offset();
+        let rebased = translate_positions(&[Position { x: 2, y: 2 }], offset)[0];
+        assert_eq!(rebased, Position { x: 0, y: 1 });
+        assert_eq!(trimmed[rebased].piece, Some(Piece::City));
+        assert_eq!(trimmed[rebased].position(), rebased);
     }
 
     #[test]
-    fn test_neighbours_ne_corner() {
-        let mut grid = Grid::<u16>::new(10, 10);
-        grid[Position { x: 8, y: 0 }] = 1;
-        grid[Position { x: 9, y: 0 }] = 2;
-        grid[Position { x: 8, y: 1 }] = 3;
-        grid[Position { x: 9, y: 1 }] = 4;
+    fn test_same_terrain() {
+        let map = ascii_map(&["+++", "+.+"]);
+        let mut other = ascii_map(&["+++", "+.+"]);
+        other.put_piece(Piece::City, Position { x: 1, y: 0 });
 
-        let mut nbrs = grid.neighbours(Position { x: 9, y: 0 });
-        assert_eq!(nbrs.next(), Some(&1));
-        assert_eq!(nbrs.next(), Some(&3));
-        assert_eq!(nbrs.next(), Some(&4));
-        assert_eq!(nbrs.next(), None);
+        assert!(map.same_terrain(&other));
+        assert_ne!(map, other);
+        assert!(!map.same_terrain(&ascii_map(&["+++", "+++"])));
+        assert!(!map.same_terrain(&ascii_map(&["+++"])));
     }
 
     #[test]
-    fn test_neighbours_sw_corner() {
-        let mut grid = Grid::<u16>::new(10, 10);
-        grid[Position { x: 0, y: 8 }] = 1;
-        grid[Position { x: 1, y: 8 }] = 2;
-        grid[Position { x: 0, y: 9 }] = 3;
-        grid[Position { x: 1, y: 9 }] = 4;
+    fn test_fingerprint() {
+        let map = ascii_map(&["+++", "+.+"]);
+        assert_eq!(map.fingerprint(), ascii_map(&["+++", "+.+"]).fingerprint());
+        assert_ne!(map.fingerprint(), ascii_map(&["+++", "+++"]).fingerprint());
+        assert_ne!(map.fingerprint(), ascii_map(&["++++.+"]).fingerprint());
 
-        let mut nbrs = grid.neighbours(Position { x: 0, y: 9 });
-        assert_eq!(nbrs.next(), Some(&1));
-        assert_eq!(nbrs.next(), Some(&2));
-        assert_eq!(nbrs.next(), Some(&4));
-        assert_eq!(nbrs.next(), None);
+        let mut other = ascii_map(&["+++", "+.+"]);
+        other.put_piece(Piece::City, Position { x: 1, y: 0 });
+        assert_ne!(map.fingerprint(), other.fingerprint());
     }
 
     #[test]
-    fn test_neighbours_se_corner() {
-        let mut grid = Grid::<u16>::new(10, 10);
-        grid[Position { x: 8, y: 8 }] = 1;
-        grid[Position { x: 9, y: 8 }] = 2;
-        grid[Position { x: 8, y: 9 }] = 3;
-        grid[Position { x: 9, y: 9 }] = 4;
+    fn test_write_styled_unknown() {
+        let map = ascii_map(&["    ", "    "]);
+        let style = TerrainStyle {
+            unknown: '?',
+            ..TerrainStyle::default()
+        };
+        let mut out = Vec::new();
+        map.write_styled(&mut out, &style).unwrap();
 
-        let mut nbrs = grid.neighbours(Position { x: 9, y: 9 });
-        assert_eq!(nbrs.next(), Some(&1));
-        assert_eq!(nbrs.next(), Some(&2));
-        assert_eq!(nbrs.next(), Some(&3));
-        assert_eq!(nbrs.next(), None);
+        assert_eq!(String::from_utf8(out).unwrap(), "????\n????\n");
+    }
+
+    #[test]
+    fn test_trailing_unknown_round_trip() {
+        let map = ascii_map(&["+.  ", ".+  "]);
+        assert_eq!(map.width, 4);
+        assert_eq!(map.to_string(), "+.  \n.+  \n");
+    }
+
+    #[test]
+    fn test_landmasses() {
+        let map = ascii_map(&["++..+", "+...+", ".+...", "....."]);
+        let landmasses = map.landmasses();
+        assert_eq!(landmasses.len(), 2);
+        assert_eq!(landmasses[0].len(), 4);
+        assert_eq!(landmasses[1].len(), 2);
+    }
+
+    #[test]
+    fn test_connect_largest_continents() {
+        let mut map = ascii_map(&[
+            "++.......+",
+            "+++....+++",
+            "+++.....++",
+            "..........",
+            "........++",
+        ]);
+        assert_eq!(map.landmasses().len(), 3);
+
+        map.connect_largest_continents(1);
+        assert_eq!(map.landmasses().len(), 2);
+
+        map.connect_largest_continents(5);
+        assert_eq!(map.landmasses().len(), 1);
+    }
+
+    #[test]
+    fn test_generate_with_one_continent() {
+        let target = ContinentTarget {
+            count: 1,
+            min_size: 20,
+            retries: 10,
+        };
+        let (map, count) =
+            Grid::generate_with_continents(30, 20, 20, 5, &target, &mut StdRng::seed_from_u64(3));
+
+        assert_eq!(count, 1);
+        let big: Vec<_> = map
+            .landmasses()
+            .into_iter()
+            .filter(|continent| continent.len() >= 20)
+            .collect();
+        assert_eq!(big.len(), 1);
+    }
+
+    #[test]
+    fn test_location_accessors() {
+        let mut map = Grid::new_random(20, 10).smooth().make_terrain(50);
+        map.place_cities();
+
+        for pos in map.positions_in(Position { x: 0, y: 0 }, 20, 10) {
+            let location = &map[pos];
+            assert_eq!(location.position(), pos);
+            assert_eq!(*location.terrain(), location.terrain);
+            assert_eq!(location.piece(), location.piece.as_ref());
+        }
+        assert!(map.map.iter().any(|l| l.piece() == Some(&Piece::City)));
+    }
+
+    #[test]
+    fn test_plan_cities() {
+        let map = Grid::new_random_with(40, 30, &mut StdRng::seed_from_u64(5))
+            .smooth_passes(4)
+            .make_terrain(40);
+        let before = map.to_string();
+
+        let cities = map.plan_cities(10, 5, &mut StdRng::seed_from_u64(5));
+        assert_eq!(cities.len(), 10);
+        for (i, pos) in cities.iter().enumerate() {
+            assert!(map[*pos].terrain == Terrain::Land);
+            for other in &cities[i + 1..] {
+                assert!(pos.distance(other) >= 5);
+            }
+        }
+        assert_eq!(map.to_string(), before);
+    }
+
+    #[test]
+    fn test_nearest_piece() {
+        let map = ascii_map(&[
+            "O.........",
+            "..........",
+            ".......O..",
+            "..........",
+            "..O.......",
+        ]);
+
+        assert_eq!(map.pieces().count(), 3);
+        assert_eq!(
+            map.nearest_piece(Position { x: 6, y: 4 }, |p| *p == Piece::City),
+            Some((Position { x: 7, y: 2 }, &Piece::City))
+        );
+        assert_eq!(
+            map.nearest_piece(Position { x: 1, y: 3 }, |p| *p == Piece::City),
+            Some((Position { x: 2, y: 4 }, &Piece::City))
+        );
+        assert_eq!(map.nearest_piece(Position { x: 1, y: 3 }, |_| false), None);
+    }
+
+    #[test]
+    fn test_coastline_length() {
+        let square = ascii_map(&[".....", ".+++.", ".+O+.", ".+++.", "....."]);
+        assert_eq!(square.coastline_length(), 12);
+
+        let cross = ascii_map(&[".....", "..+..", ".+++.", "..+..", "....."]);
+        assert_eq!(cross.coastline_length(), 12);
+
+        let shore = ascii_map(&["++", ".."]);
+        assert_eq!(shore.coastline_length(), 2);
+    }
+
+    #[test]
+    fn test_custom_generator() {
+        struct Flat;
+
+        impl TerrainGenerator for Flat {
+            fn generate(&self, width: u16, height: u16, _rng: &mut dyn RngCore) -> Grid<u16> {
+                Grid::new(width, height)
+            }
+        }
+
+        let map = Flat
+            .generate(8, 6, &mut rand::thread_rng())
+            .make_terrain(70);
+        assert!(map.map.iter().all(|l| l.terrain == Terrain::Water));
+    }
+
+    #[test]
+    fn test_cities_by_distance() {
+        let map = ascii_map(&[
+            "O.........",
+            "..........",
+            ".......O..",
+            "..........",
+            "..O......O",
+        ]);
+        let order: Vec<_> = map
+            .cities_by_distance(Position { x: 6, y: 4 })
+            .into_iter()
+            .map(|(pos, _)| pos)
+            .collect();
+
+        assert_eq!(
+            order,
+            vec![
+                Position { x: 7, y: 2 },
+                Position { x: 9, y: 4 },
+                Position { x: 2, y: 4 },
+                Position { x: 0, y: 0 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_line_between() {
+        assert_eq!(
+            line_between(Position { x: 0, y: 0 }, Position { x: 3, y: 1 }),
+            vec![
+                Position { x: 0, y: 0 },
+                Position { x: 1, y: 0 },
+                Position { x: 2, y: 1 },
+                Position { x: 3, y: 1 },
+            ]
+        );
+        assert_eq!(
+            line_between(Position { x: 2, y: 2 }, Position { x: 2, y: 2 }),
+            vec![Position { x: 2, y: 2 }]
+        );
+    }
+
+    #[test]
+    fn test_visible_from_peak() {
+        let mut map = ascii_map(&["+++++++"]);
+        let target = Position { x: 4, y: 0 };
+        map[Position { x: 2, y: 0 }].height = 100;
+
+        let low = Position { x: 0, y: 0 };
+        assert!(!map.visible_from(low, 5).contains(&target));
+        assert!(map.visible_from(low, 5).contains(&Position { x: 2, y: 0 }));
+
+        map[low].height = 900;
+        assert!(map.visible_from(low, 5).contains(&target));
+    }
+
+    #[test]
+    fn test_visible_from_height_extends_radius() {
+        let mut map = ascii_map(&["+++++++++"]);
+        let observer = Position { x: 0, y: 0 };
+        assert_eq!(map.visible_from(observer, 3).len(), 4);
+
+        map[observer].height = 2 * SIGHT_HEIGHT_STEP;
+        assert_eq!(map.visible_from(observer, 3).len(), 6);
+        assert_eq!(map.visible_from(observer, u16::MAX).len(), 9);
+        assert!(map.visible_from(Position { x: 9, y: 0 }, 3).is_empty());
+        assert!(map.visible_from(Position { x: -1, y: 0 }, 3).is_empty());
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut map = ascii_map(&["+O+", "..."]);
+        assert!(map.validate().is_ok());
+
+        map.put_piece(Piece::City, Position { x: 1, y: 1 });
+        map[Position { x: 2, y: 0 }].terrain = Terrain::Unknown;
+        map.put_piece(Piece::City, Position { x: 2, y: 0 });
+        map.map.pop();
+
+        let errors = map.validate().unwrap_err();
+        assert_eq!(errors.len(), 3);
+        assert!(matches!(
+            errors[0],
+            EmpyreError::SizeMismatch {
+                expected: 6,
+                found: 5
+            }
+        ));
+        assert!(matches!(
+            errors[1],
+            EmpyreError::MisplacedPiece {
+                pos: Position { x: 2, y: 0 },
+                terrain: Terrain::Unknown
+            }
+        ));
+        assert!(matches!(
+            errors[2],
+            EmpyreError::MisplacedPiece {
+                pos: Position { x: 1, y: 1 },
+                terrain: Terrain::Water
+            }
+        ));
     }
 
     #[test]
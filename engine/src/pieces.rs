@@ -1,12 +1,30 @@
 use std::fmt::Display;
 
+use crate::{maps::Terrain, theme::Theme};
+
+#[derive(Clone, PartialEq, Debug)]
 pub enum Piece {
     City
 }
 
+impl Piece {
+    pub fn can_occupy(&self, terrain: &Terrain) -> bool {
+        match self {
+            Piece::City => *terrain == Terrain::Land,
+        }
+    }
+
+    // Reads the glyphs of the default theme, which `Display` writes.
+    pub(crate) fn from_glyph(c: char) -> Option<Self> {
+        [Piece::City]
+            .into_iter()
+            .find(|piece| Theme::default().piece_glyph(piece) == c)
+    }
+}
+
 impl Display for Piece {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "O")?;
+        write!(f, "{}", Theme::default().piece_glyph(self))?;
         Ok(())
     }
 }
\ No newline at end of file
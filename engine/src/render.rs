@@ -0,0 +1,100 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter},
+    path::Path,
+};
+
+use crate::maps::{Grid, Position};
+
+impl<T> Grid<T>
+where
+    T: Copy + Into<f64>,
+{
+    // Draws each cell as a `tile_px` square, coloured from blue at the grid's
+    // lowest value through green to red at its highest.
+    pub fn render_heatmap_png(&self, path: &Path, tile_px: u32) -> io::Result<()> {
+        let values: Vec<f64> = self
+            .positions_in(Position::new(0, 0), self.width(), self.height())
+            .map(|pos| self[pos].into())
+            .collect();
+        let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let range = if max > min { max - min } else { 1.0 };
+
+        let too_large = || io::Error::new(io::ErrorKind::InvalidInput, "heatmap is too large");
+        let width = (self.width() as u32)
+            .checked_mul(tile_px)
+            .ok_or_else(too_large)?;
+        let height = (self.height() as u32)
+            .checked_mul(tile_px)
+            .ok_or_else(too_large)?;
+        let len = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|len| len.checked_mul(3))
+            .ok_or_else(too_large)?;
+        let mut pixels = Vec::with_capacity(len);
+        for py in 0..height {
+            for px in 0..width {
+                let cell =
+                    (py / tile_px) as usize * self.width() as usize + (px / tile_px) as usize;
+                pixels.extend(heat_colour((values[cell] - min) / range));
+            }
+        }
+
+        let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(io::Error::other)?;
+        writer.write_image_data(&pixels).map_err(io::Error::other)?;
+        writer.finish().map_err(io::Error::other)
+    }
+}
+
+// Maps 0.0..=1.0 onto blue, green, red.
+fn heat_colour(t: f64) -> [u8; 3] {
+    let t = t.clamp(0.0, 1.0);
+    if t < 0.5 {
+        let s = t * 2.0;
+        [0, (255.0 * s) as u8, (255.0 * (1.0 - s)) as u8]
+    } else {
+        let s = (t - 0.5) * 2.0;
+        [(255.0 * s) as u8, (255.0 * (1.0 - s)) as u8, 0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::test_util::temp_path;
+
+    #[test]
+    fn test_heat_colour() {
+        assert_eq!(heat_colour(0.0), [0, 0, 255]);
+        assert_eq!(heat_colour(0.5), [0, 255, 0]);
+        assert_eq!(heat_colour(1.0), [255, 0, 0]);
+    }
+
+    #[test]
+    fn test_render_heatmap_png() {
+        let grid = Grid::from_vec(4, 2, (0..8u16).map(|v| v * 100).collect()).unwrap();
+        let path = temp_path("heatmap.png");
+        grid.render_heatmap_png(&path, 3).unwrap();
+
+        let decoder = png::Decoder::new(io::BufReader::new(File::open(&path).unwrap()));
+        let reader = decoder.read_info().unwrap();
+        assert_eq!((reader.info().width, reader.info().height), (12, 6));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_render_heatmap_png_too_large() {
+        let path = temp_path("huge.png");
+        let wide = Grid::from_vec(2, 1, vec![0u16, 1]).unwrap();
+        assert!(wide.render_heatmap_png(&path, u32::MAX).is_err());
+        let single = Grid::from_vec(1, 1, vec![0u16]).unwrap();
+        assert!(single.render_heatmap_png(&path, u32::MAX).is_err());
+        assert!(!path.exists());
+    }
+}
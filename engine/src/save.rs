@@ -0,0 +1,362 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use crate::{
+    error::EmpyreError,
+    maps::{Grid, Location},
+};
+
+pub const SAVE_VERSION: u32 = 1;
+
+const SAVE_MAGIC: &str = "empyre";
+
+const ARCHIVE_MAGIC: &str = "empyre-archive";
+
+impl Grid<Location> {
+    // A save holds no dimensions apart from its rows, so maps without any
+    // rows or columns are refused rather than saved as something that
+    // cannot be loaded.
+    pub fn save_to_file(&self, path: impl AsRef<Path>) -> Result<(), EmpyreError> {
+        if self.width() == 0 || self.height() == 0 {
+            return Err(empty_map_error("map"));
+        }
+        let mut file = BufWriter::new(File::create(path)?);
+        writeln!(file, "{SAVE_MAGIC} {SAVE_VERSION}")?;
+        self.write_to(&mut file)?;
+        file.flush()?;
+        Ok(())
+    }
+
+    pub fn load_from_file(path: impl AsRef<Path>) -> Result<Self, EmpyreError> {
+        let contents = fs::read_to_string(path)?;
+        let (header, body) = contents.split_once('\n').unwrap_or((&contents, ""));
+        let version = parse_header(header)?;
+        Grid::from_ascii(&migrate(version, body.to_string())?)
+    }
+
+    // Run-length encodes the map glyphs as `<width>x<height>:` followed by
+    // rows of `<count><glyph>` runs separated by `/`, e.g. `3x2:2.1+/3.`.
+    pub fn to_rle(&self) -> String {
+        let rows: Vec<String> = self
+            .to_string()
+            .lines()
+            .map(|row| {
+                let mut encoded = String::new();
+                let mut chars = row.chars().peekable();
+                while let Some(c) = chars.next() {
+                    let mut run = 1;
+                    while chars.next_if_eq(&c).is_some() {
+                        run += 1;
+                    }
+                    encoded.push_str(&format!("{run}{c}"));
+                }
+                encoded
+            })
+            .collect();
+        format!("{}x{}:{}", self.width(), self.height(), rows.join("/"))
+    }
+
+    pub fn from_rle(s: &str) -> Result<Self, EmpyreError> {
+        let bad = |reason: &str| EmpyreError::InvalidMap(format!("bad run-length map: {reason}"));
+
+        let (header, body) = s.split_once(':').ok_or_else(|| bad("missing header"))?;
+        let (width, height) = header
+            .split_once('x')
+            .and_then(|(w, h)| Some((w.parse::<u16>().ok()?, h.parse::<u16>().ok()?)))
+            .ok_or_else(|| bad("bad dimensions"))?;
+        // What `to_rle` writes for a map with no rows or columns, e.g. one
+        // that `trim_water_border` emptied.
+        if width == 0 || height == 0 {
+            if !body.chars().all(|c| c == '/') {
+                return Err(bad("dimensions do not match the header"));
+            }
+            return Grid::from_vec(width, height, Vec::new());
+        }
+
+        // Runs are checked against the header before they are expanded, so a
+        // short string cannot claim an enormous map.
+        let mut ascii = String::new();
+        for (y, row) in body.split('/').enumerate() {
+            if y >= height as usize {
+                return Err(bad("more rows than the header allows"));
+            }
+            let mut run = String::new();
+            let mut row_len: usize = 0;
+            for c in row.chars() {
+                if c.is_ascii_digit() {
+                    run.push(c);
+                } else {
+                    let count: usize = run.parse().map_err(|_| bad("missing run length"))?;
+                    row_len = row_len.saturating_add(count);
+                    if row_len > width as usize {
+                        return Err(bad("row is wider than the header allows"));
+                    }
+                    ascii.extend(std::iter::repeat_n(c, count));
+                    run.clear();
+                }
+            }
+            if !run.is_empty() {
+                return Err(bad("run length without a glyph"));
+            }
+            ascii.push('\n');
+        }
+
+        let grid = Grid::from_ascii(&ascii)?;
+        if grid.width() != width || grid.height() != height {
+            return Err(bad("dimensions do not match the header"));
+        }
+        Ok(grid)
+    }
+}
+
+// An archive starts with `empyre-archive <version>`, then a count line and
+// one `<height> <name>` index line per map, then each map's rows in turn.
+// Errors come back as `EmpyreError`, as they do from `save_to_file` and
+// `load_from_file`, so a bad archive is told apart from a failed read.
+pub fn save_archive(
+    path: impl AsRef<Path>,
+    maps: &[(String, &Grid<Location>)],
+) -> Result<(), EmpyreError> {
+    if let Some((name, _)) = maps.iter().find(|(name, _)| name.contains('\n')) {
+        return Err(EmpyreError::InvalidMap(format!(
+            "map name {name:?} spans more than one line"
+        )));
+    }
+    if let Some((name, _)) = maps
+        .iter()
+        .find(|(_, map)| map.width() == 0 || map.height() == 0)
+    {
+        return Err(empty_map_error(&format!("map {name:?}")));
+    }
+
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(file, "{ARCHIVE_MAGIC} {SAVE_VERSION}")?;
+    writeln!(file, "{}", maps.len())?;
+    for (name, map) in maps {
+        writeln!(file, "{} {name}", map.height())?;
+    }
+    for (_, map) in maps {
+        map.write_to(&mut file)?;
+    }
+    file.flush()?;
+    Ok(())
+}
+
+pub fn load_archive(path: impl AsRef<Path>) -> Result<Vec<(String, Grid<Location>)>, EmpyreError> {
+    let bad = |reason: &str| EmpyreError::InvalidMap(format!("bad archive: {reason}"));
+
+    let contents = fs::read_to_string(path)?;
+    let mut lines = contents.lines();
+    let version = match lines.next().and_then(|header| header.split_once(' ')) {
+        Some((ARCHIVE_MAGIC, version)) => version
+            .trim()
+            .parse::<u32>()
+            .map_err(|_| bad("bad version tag"))?,
+        _ => return Err(bad("missing archive header")),
+    };
+    if version == 0 || version > SAVE_VERSION {
+        return Err(EmpyreError::UnsupportedVersion(version));
+    }
+
+    let count: usize = lines
+        .next()
+        .and_then(|line| line.trim().parse().ok())
+        .ok_or_else(|| bad("missing map count"))?;
+    let index = (0..count)
+        .map(|_| {
+            let (height, name) = lines
+                .next()
+                .and_then(|line| line.split_once(' '))
+                .ok_or_else(|| bad("missing index entry"))?;
+            let height: usize = height.parse().map_err(|_| bad("bad map height"))?;
+            Ok((name.to_string(), height))
+        })
+        .collect::<Result<Vec<_>, EmpyreError>>()?;
+
+    index
+        .into_iter()
+        .map(|(name, height)| {
+            let rows: Vec<&str> = lines.by_ref().take(height).collect();
+            if rows.len() != height {
+                return Err(bad("archive ends part way through a map"));
+            }
+            let body = migrate(version, rows.join("\n") + "\n")?;
+            Ok((name, Grid::from_ascii(&body)?))
+        })
+        .collect()
+}
+
+fn empty_map_error(what: &str) -> EmpyreError {
+    EmpyreError::InvalidMap(format!("{what} has no rows or columns and cannot be saved"))
+}
+
+fn parse_header(header: &str) -> Result<u32, EmpyreError> {
+    match header.split_once(' ') {
+        Some((SAVE_MAGIC, version)) => version
+            .trim()
+            .parse()
+            .map_err(|_| EmpyreError::InvalidMap(format!("bad version tag {version:?}"))),
+        _ => Err(EmpyreError::InvalidMap("missing save header".to_string())),
+    }
+}
+
+// Brings the body of an older save up to the current format, one version at a time.
+fn migrate(mut version: u32, mut body: String) -> Result<String, EmpyreError> {
+    if version == 0 || version > SAVE_VERSION {
+        return Err(EmpyreError::UnsupportedVersion(version));
+    }
+
+    while version < SAVE_VERSION {
+        body = match version {
+            1 => migrate_v1_to_v2(body),
+            _ => return Err(EmpyreError::UnsupportedVersion(version)),
+        };
+        version += 1;
+    }
+    Ok(body)
+}
+
+// Version 2 does not exist yet; this is where its conversion will go.
+fn migrate_v1_to_v2(body: String) -> String {
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::test_util::temp_path;
+
+    fn temp_save(name: &str, contents: &str) -> PathBuf {
+        let path = temp_path(name);
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_save_round_trip() {
+        let mut map = Grid::new_random(20, 10).smooth().make_terrain(50);
+        map.place_cities();
+
+        let path = temp_path("round-trip");
+        map.save_to_file(&path).unwrap();
+        let loaded = Grid::load_from_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.to_string(), map.to_string());
+    }
+
+    #[test]
+    fn test_rle_round_trip() {
+        let mut map = Grid::new_random(30, 20).smooth_passes(3).make_terrain(50);
+        map.place_cities();
+
+        let decoded = Grid::from_rle(&map.to_rle()).unwrap();
+        assert_eq!(decoded.to_string(), map.to_string());
+    }
+
+    #[test]
+    fn test_rle_all_water() {
+        let map = Grid::from_ascii(&format!("{}\n", ".".repeat(100)).repeat(60)).unwrap();
+        let rle = map.to_rle();
+
+        assert_eq!(rle, format!("100x60:{}", vec!["100."; 60].join("/")));
+        assert!(rle.len() < 400);
+    }
+
+    #[test]
+    fn test_rle_rejects_bad_input() {
+        assert!(Grid::from_rle("3x1:2.").is_err());
+        assert!(Grid::from_rle("3x1:.++").is_err());
+        assert!(Grid::from_rle("2.1+").is_err());
+    }
+
+    #[test]
+    fn test_rle_rejects_runs_past_the_header() {
+        assert!(Grid::from_rle("2x1:18446744073709551615.").is_err());
+        assert!(Grid::from_rle("2x1:1.18446744073709551615+").is_err());
+        assert!(Grid::from_rle("3x1:2.2+").is_err());
+        assert!(Grid::from_rle("1x2:1./1./1.").is_err());
+        assert!(Grid::from_rle("2x2:2./1.1+").is_ok());
+    }
+
+    #[test]
+    fn test_empty_maps() {
+        let empty = Grid::from_ascii("..\n..\n").unwrap().trim_water_border();
+        assert_eq!(empty.to_rle(), "0x0:");
+        assert_eq!(Grid::from_rle(&empty.to_rle()).unwrap(), empty);
+        let narrow = Grid::from_rle("0x3:").unwrap();
+        assert_eq!((narrow.width(), narrow.height()), (0, 3));
+        assert!(Grid::from_rle("0x1:2.").is_err());
+
+        let path = temp_path("empty");
+        assert!(matches!(
+            empty.save_to_file(&path),
+            Err(EmpyreError::InvalidMap(_))
+        ));
+        assert!(matches!(
+            save_archive(&path, &[("nothing".to_string(), &empty)]),
+            Err(EmpyreError::InvalidMap(_))
+        ));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_archive_round_trip() {
+        let mut first = Grid::new_random(20, 10).smooth().make_terrain(50);
+        first.place_cities();
+        let second = Grid::from_ascii("+..\n.+ \n").unwrap();
+
+        let path = temp_path("archive");
+        save_archive(
+            &path,
+            &[
+                ("The Isles".to_string(), &first),
+                ("tiny".to_string(), &second),
+            ],
+        )
+        .unwrap();
+        let loaded = load_archive(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded[0].0, "The Isles");
+        assert_eq!(loaded[0].1.to_string(), first.to_string());
+        assert_eq!(loaded[1].0, "tiny");
+        assert_eq!(loaded[1].1.to_string(), second.to_string());
+    }
+
+    #[test]
+    fn test_load_truncated_archive() {
+        let path = temp_save(
+            "truncated-archive",
+            "empyre-archive 1\n1\n3 short\n+.\n.+\n",
+        );
+        let result = load_archive(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(EmpyreError::InvalidMap(_))));
+    }
+
+    #[test]
+    fn test_load_unsupported_version() {
+        let path = temp_save("unsupported-version", "empyre 99\n+.\n.+\n");
+        let result = Grid::load_from_file(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(EmpyreError::UnsupportedVersion(99))));
+    }
+
+    #[test]
+    fn test_load_bad_version_tag() {
+        let path = temp_save("bad-version-tag", "empyre one\n+.\n.+\n");
+        let result = Grid::load_from_file(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(EmpyreError::InvalidMap(_))));
+    }
+}
@@ -0,0 +1,6 @@
+use std::path::PathBuf;
+
+// A per-process scratch path, so parallel test runs don't trip over each other.
+pub(crate) fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("empyre-{}-{name}", std::process::id()))
+}
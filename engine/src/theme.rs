@@ -0,0 +1,118 @@
+use std::{fs, path::Path};
+
+use crate::{error::EmpyreError, maps::TerrainStyle, pieces::Piece};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Theme {
+    pub terrain: TerrainStyle,
+    pub city: char,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            terrain: TerrainStyle::default(),
+            city: 'O',
+        }
+    }
+}
+
+impl Theme {
+    pub fn piece_glyph(&self, piece: &Piece) -> char {
+        match piece {
+            Piece::City => self.city,
+        }
+    }
+
+    // Reads a flat TOML table of `key = "glyph"` lines, one per glyph to
+    // override, e.g. `water = "~"`. Only the little TOML a theme needs is
+    // understood: blank lines, `#` comments and one-character basic strings
+    // without escapes.
+    pub fn parse(s: &str) -> Result<Self, EmpyreError> {
+        let mut theme = Theme::default();
+        for (n, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let bad = |reason: &str| EmpyreError::InvalidTheme(format!("line {}: {reason}", n + 1));
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| bad("expected key = value"))?;
+            let (value, rest) = value
+                .trim()
+                .strip_prefix('"')
+                .and_then(|v| v.split_once('"'))
+                .ok_or_else(|| bad("a glyph must be a quoted string"))?;
+            let rest = rest.trim();
+            if !rest.is_empty() && !rest.starts_with('#') {
+                return Err(bad("unexpected text after the glyph"));
+            }
+            let mut chars = value.chars();
+            let glyph = match (chars.next(), chars.next()) {
+                (Some('\\'), _) => return Err(bad("escapes are not supported")),
+                (Some(c), None) => c,
+                _ => return Err(bad("a glyph must be a single character")),
+            };
+
+            match key.trim() {
+                "land" => theme.terrain.land = glyph,
+                "water" => theme.terrain.water = glyph,
+                "unknown" => theme.terrain.unknown = glyph,
+                "mountain" => theme.terrain.mountain = glyph,
+                "city" => theme.city = glyph,
+                other => return Err(bad(&format!("unknown key {other:?}"))),
+            }
+        }
+        Ok(theme)
+    }
+
+    pub fn load_from_file(path: impl AsRef<Path>) -> Result<Self, EmpyreError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{maps::Grid, test_util::temp_path};
+
+    #[test]
+    fn test_load_theme() {
+        let path = temp_path("theme");
+        fs::write(
+            &path,
+            "# dark theme\nwater = \"~\"\nland = \"#\" # solid\n\ncity = \"@\"\n",
+        )
+        .unwrap();
+        let theme = Theme::load_from_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let map = Grid::from_ascii("+.O\n..+\n").unwrap();
+        let mut out = Vec::new();
+        map.write_themed(&mut out, &theme).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "#~@\n~~#\n");
+    }
+
+    #[test]
+    fn test_default_theme_matches_display() {
+        let map = Grid::from_ascii("+.O\n^ +\n").unwrap();
+        let mut out = Vec::new();
+        map.write_themed(&mut out, &Theme::default()).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), map.to_string());
+    }
+
+    #[test]
+    fn test_parse_theme_rejects_bad_lines() {
+        assert!(matches!(
+            Theme::parse("forest = ^"),
+            Err(EmpyreError::InvalidTheme(_))
+        ));
+        assert!(Theme::parse("land = \"++\"").is_err());
+        assert!(Theme::parse("land = +").is_err());
+        assert!(Theme::parse("land = \"+\" x").is_err());
+        assert!(Theme::parse("land = \"\\\\\"").is_err());
+        assert!(Theme::parse("land").is_err());
+    }
+}
@@ -1,10 +1,14 @@
+use std::io::{self, BufWriter};
+
 use clap::Parser;
-use engine::{Grid, DEFAULT_MAP_HEIGHT, DEFAULT_MAP_WIDTH};
+use engine::{Grid, Location, DEFAULT_MAP_HEIGHT, DEFAULT_MAP_WIDTH};
+use rand::{rngs::StdRng, SeedableRng};
 
 #[derive(Parser)]
 #[command(name = "empyre", author, version, about, long_about = None)]
 struct Cli {
     #[arg(short = 'w',
+        long,
         default_value = "70",
         help = "Must be in range 0..90",
         value_parser = clap::value_parser!(u16).range(0..=90))]
@@ -16,17 +20,77 @@ struct Cli {
         help = "Must be greater or equal to zero"
     )]
     smooth: Option<u16>,
+
+    #[arg(short = 'l',
+        long,
+        conflicts_with = "water",
+        help = "Land percentage, used in place of -w; must be in range 10..100",
+        value_parser = clap::value_parser!(u16).range(10..=100))]
+    land: Option<u16>,
+
+    #[arg(long, help = "Seed for the map; a random one is picked if omitted")]
+    seed: Option<u64>,
+}
+
+// Everything needed to generate the same map again.
+fn describe(cli: &Cli, seed: u64) -> String {
+    let ratio = match cli.land {
+        Some(land) => format!("land={land}"),
+        None => format!("water={}", cli.water.unwrap()),
+    };
+    format!(
+        "width={DEFAULT_MAP_WIDTH} height={DEFAULT_MAP_HEIGHT} {ratio} smooth={} seed={seed}",
+        cli.smooth.unwrap()
+    )
+}
+
+fn generate(cli: &Cli, seed: u64) -> Grid<Location> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let grid = Grid::new_random_with(DEFAULT_MAP_WIDTH, DEFAULT_MAP_HEIGHT, &mut rng)
+        .smooth_passes(cli.smooth.unwrap());
+
+    let mut map = match cli.land {
+        Some(land) => grid.make_terrain_land_ratio(land),
+        None => grid.make_terrain(cli.water.unwrap()),
+    };
+    map.place_cities_with(&mut rng);
+    map
 }
 
 fn main() {
     let cli = Cli::parse();
-    let mut grid = Grid::new_random(DEFAULT_MAP_WIDTH, DEFAULT_MAP_HEIGHT);
+    let seed = cli.seed.unwrap_or_else(rand::random);
+    eprintln!("{}", describe(&cli, seed));
+
+    let map = generate(&cli, seed);
+    let mut out = BufWriter::new(io::stdout().lock());
+    map.write_to(&mut out).expect("failed to write map");
+}
 
-    for _ in 0..cli.smooth.unwrap() {
-        grid = grid.smooth();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_seed_is_reported_and_reproducible() {
+        let cli = Cli::parse_from(["empyre", "-w", "60", "--seed", "42"]);
+        let seed = cli.seed.unwrap();
+        assert_eq!(
+            describe(&cli, seed),
+            format!(
+                "width={DEFAULT_MAP_WIDTH} height={DEFAULT_MAP_HEIGHT} water=60 smooth=5 seed=42"
+            )
+        );
+        assert_eq!(generate(&cli, seed), generate(&cli, seed));
     }
 
-    let mut map = grid.make_terrain(cli.water.unwrap());
-    map.place_cities();
-    println!("{map}");
+    #[test]
+    fn test_land_flag() {
+        let cli = Cli::parse_from(["empyre", "--land", "30", "--seed", "1"]);
+        assert!(describe(&cli, 1).contains(" land=30 "));
+        assert_ne!(generate(&cli, 1), generate(&Cli::parse_from(["empyre"]), 1));
+
+        assert!(Cli::try_parse_from(["empyre", "-w", "50", "-l", "30"]).is_err());
+        assert!(Cli::try_parse_from(["empyre", "-l", "5"]).is_err());
+    }
 }